@@ -31,7 +31,8 @@ fn main() {
         outputs: &[ComputePipelineOutput::StorageTexture(&texture)],
         shader: ShaderSource::File(std::path::Path::new("examples/complex/shader.wgsl")),
         shader_entry: "render",
-        dispatch_count: texture.dispatch_count((16, 16)),
+        dispatch_count: texture.dispatch_count((16, 16)).into(),
+        ..Default::default()
     });
 
     let screen_copier = ctx.create_screen_copier(&texture, ScalingType::Linear);
@@ -44,6 +45,7 @@ fn main() {
         pub origin_world: [f32; 2],
     }
     let mut translation_state: Option<TranslationState> = None;
+    let mut screenshot: Option<ReadbackTicket<u8>> = None;
     let start_time = std::time::Instant::now();
 
     // We use run_ex to separate the update loop from the render loop.
@@ -83,8 +85,14 @@ fn main() {
                 let mut encoder = ctx.device.create_command_encoder(&Default::default());
                 ctx.copy_texture_to_texture(&mut encoder, &screenshot_copier);
                 ctx.queue.submit(std::iter::once(encoder.finish()));
-                screenshot_texture.read_to_png(&ctx, std::path::Path::new("complex.png"));
+                screenshot = Some(screenshot_texture.read_async(&ctx));
+            }
+
+            // Reading the screenshot back takes a few frames, so check on it each update.
+            if let Some(pixels) = screenshot.as_ref().and_then(|ticket| ticket.poll(&ctx)) {
+                screenshot_texture.write_png(&pixels, std::path::Path::new("complex.png"));
                 println!("Saved screenshot!");
+                screenshot = None;
             }
 
             if input.just_pressed(Key::KeyQ) { 
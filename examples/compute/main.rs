@@ -39,25 +39,28 @@ fn main() {
         points.push(random_unit_vector());
     }
 
-    let points_buffer_in = ctx.create_storage_buffer(&points);
-    let points_buffer_out = ctx.create_storage_buffer_empty::<Point>(points.len());
+    let points_buffer = PingPong::new(
+        ctx.create_storage_buffer(&points),
+        ctx.create_storage_buffer_empty::<Point>(points.len()),
+    );
 
     let update_points = ctx.create_compute_pipeline(ComputePipelineDescriptor {
-        inputs: &[PipelineInput::StorageBuffer(&points_buffer_in)],
-        outputs: &[ComputePipelineOutput::StorageBuffer(&points_buffer_out)],
-        shader: include_str!("shader.wgsl").into(),
+        inputs: &[PipelineInput::PingPongBuffer(&points_buffer)],
+        outputs: &[ComputePipelineOutput::PingPongBuffer(&points_buffer)],
+        shader: ShaderSource::Str(include_str!("shader.wgsl")),
         shader_entry: "update",
-        dispatch_count: points_buffer_in.dispatch_count(16),
+        dispatch_count: points_buffer.a.dispatch_count(16).into(),
+        ..Default::default()
     });
 
     let mut encoder = ctx.device.create_command_encoder(&Default::default());
     for _ in 0..1000 {
         ctx.run_compute_pass(&mut encoder, &[&update_points]);
-        ctx.copy_buffer_to_buffer(&mut encoder, &points_buffer_out, &points_buffer_in);
+        points_buffer.swap();
     }
     ctx.queue.submit(std::iter::once(encoder.finish()));
 
-    let points = points_buffer_in.read_to_vec::<Point>(&ctx);
+    let points = points_buffer.front().read_to_vec(&ctx);
 
     for p in points {
         println!("{}, {}, {}", p.x, p.y, p.z);
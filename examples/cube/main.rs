@@ -54,7 +54,7 @@ fn main() {
         shader: ShaderSource::Str(include_str!("shader.wgsl")),
         shader_vertex_entry: "vertex",
         shader_fragment_entry: "fragment",
-        output_format: OUTPUT_TEXTURE_FORMAT,
+        output_format: ctx.output_texture_format,
     });
 
     ctx.run(
@@ -0,0 +1,13 @@
+struct Info {
+    mouse_pos: vec2<f32>,
+    mouse_flags: u32,
+}
+
+const WIDTH: i32 = SCREEN_WIDTH;
+const HEIGHT: i32 = SCREEN_HEIGHT;
+
+const WIDTH_F: f32 = f32(WIDTH);
+const HEIGHT_F: f32 = f32(HEIGHT);
+
+const MOUSE_LEFT_DOWN: u32 = 1u << 0u;
+const MOUSE_RIGHT_DOWN: u32 = 1u << 1u;
@@ -28,11 +28,11 @@ const MOUSE_RIGHT_DOWN: u32 = 1 << 1;
 fn f32_rng_buf(rng: &mut fastrand::Rng, buf: &mut [f32]) {
     rng.fill(bytemuck::cast_slice_mut(buf));
 
-    for i in 0..buf.len() {
-        let n: u32 = bytemuck::cast(buf[i]);
+    for x in buf.iter_mut() {
+        let n: u32 = bytemuck::cast(*x);
         let b = 32;
-        let f = core::f32::MANTISSA_DIGITS - 1;
-        buf[i] = f32::from_bits((1 << (b - 2)) - (1 << f) + (n >> (b - f))) - 1.0;
+        let f = f32::MANTISSA_DIGITS - 1;
+        *x = f32::from_bits((1 << (b - 2)) - (1 << f) + (n >> (b - f))) - 1.0;
     }
 }
 
@@ -45,8 +45,8 @@ fn main() {
     rng.fill(bytemuck::cast_slice_mut(&mut prng));
 
     let mut buddies = [ZERO_BUDDY; BUDDY_COUNT];
-    for i in 0..BUDDY_COUNT { 
-        buddies[i].pos = [fastrand::f32()*(W as f32), fastrand::f32()*(H as f32)]; 
+    for buddy in buddies.iter_mut() { 
+        buddy.pos = [fastrand::f32()*(W as f32), fastrand::f32()*(H as f32)]; 
     }
 
     let prng_buf = ctx.create_storage_buffer(&prng);
@@ -61,44 +61,56 @@ fn main() {
     let mut prng_tex_data = [0f32; (2*W*H) as usize];
     let prng_texture = ctx.create_storage_texture((W, H), StorageTextureFormat::Rg32Float);
 
-    let field = ctx.create_storage_texture((W, H), StorageTextureFormat::Rgba32Float);
-    let field_out = ctx.create_storage_texture((W, H), StorageTextureFormat::Rgba32Float);
+    let field = PingPong::new(
+        ctx.create_storage_texture((W, H), StorageTextureFormat::Rgba32Float),
+        ctx.create_storage_texture((W, H), StorageTextureFormat::Rgba32Float),
+    );
 
-    let update_field = ctx.create_compute_pipeline(ComputePipelineDescriptor {
+    let (w, h) = (W.to_string(), H.to_string());
+    let screen_size_defines = [("SCREEN_WIDTH", w.as_str()), ("SCREEN_HEIGHT", h.as_str())];
+
+    let mut update_field = ctx.create_compute_pipeline(ComputePipelineDescriptor {
         inputs: &[
             PipelineInput::Uniform(&info_uniform),
             PipelineInput::StorageTexture(&prng_texture),
-            PipelineInput::StorageTexture(&field),
+            PipelineInput::PingPongTexture(&field),
         ],
         outputs: &[
-            ComputePipelineOutput::StorageTexture(&field_out),
+            ComputePipelineOutput::PingPongTexture(&field),
             ComputePipelineOutput::StorageTexture(&texture),
         ],
         shader: ShaderSource::File(std::path::Path::new("examples/game/per_unit.wgsl")),
+        shader_defines: &screen_size_defines,
         shader_entry: "update",
-        dispatch_count: field.dispatch_count((16, 16)),
+        dispatch_count: field.a.dispatch_count((16, 16)).into(),
+        ..Default::default()
     });
 
-    let update_buddies = ctx.create_compute_pipeline(ComputePipelineDescriptor {
+    let _update_buddies = ctx.create_compute_pipeline(ComputePipelineDescriptor {
         inputs: &[
             PipelineInput::StorageBuffer(&prng_buf),
             PipelineInput::Uniform(&info_uniform),
-            PipelineInput::StorageTexture(&field_out),
+            PipelineInput::PingPongTexture(&field),
         ],
         outputs: &[
             ComputePipelineOutput::StorageBuffer(&buddies_buf),
             ComputePipelineOutput::StorageTexture(&texture),
         ],
         shader: ShaderSource::File(std::path::Path::new("examples/game/per_buddy.wgsl")),
+        shader_defines: &screen_size_defines,
         shader_entry: "update",
-        dispatch_count: buddies_buf.dispatch_count(16),
+        dispatch_count: buddies_buf.dispatch_count(16).into(),
+        ..Default::default()
     });
 
     let screen_copier = ctx.create_screen_copier(&texture, ScalingType::Nearest);
-    let field_copier = ctx.create_texture_copier_fast(&field_out, &field);
 
     ctx.run_ex((W*SCALE, H*SCALE), 60, |ev| match ev {
         WindowEvent::Update { input, .. } => {
+            if let Err(e) = update_field.reload(&ctx) {
+                eprintln!("{}", e);
+            }
+
             info_uniform.update(&ctx, &Info {
                 mouse_pos: match input.mouse_position {
                     None => [-1.0, -1.0],
@@ -132,7 +144,7 @@ fn main() {
             prng_texture.update(&ctx, &prng_tex_data);
             ctx.run_compute_pass(&mut encoder, &[&update_field]);
             ctx.copy_texture_to_screen(&mut encoder, &screen_copier, output);
-            ctx.copy_texture_to_texture(&mut encoder, &field_copier);
+            field.swap();
             ctx.queue.submit(std::iter::once(encoder.finish()));
 
             None
@@ -3,23 +3,11 @@ struct Buddy {
     vel: vec2<f32>,
 }
 
-struct Info {
-    mouse_pos: vec2<f32>,
-    mouse_flags: u32,
-}
-
-const WIDTH: i32 = 1920 / 4;
-const HEIGHT: i32 = 1080 / 4;
-
-const WIDTH_F: f32 = 1920.0 / 4.0;
-const HEIGHT_F: f32 = 1080.0 / 4.0;
+#include "common.wgsl"
 
 const FRICTION: f32 = 0.6;
 const MASS: f32 = 1.0;
 
-const MOUSE_LEFT_DOWN: u32 = 1u << 0u;
-const MOUSE_RIGHT_DOWN: u32 = 1u << 1u;
-
 // read
 @group(0) @binding(0) var<storage, read> prng: array<u32>;
 @group(0) @binding(1) var<uniform> info: Info;
@@ -1,16 +1,4 @@
-struct Info {
-    mouse_pos: vec2<f32>,
-    mouse_flags: u32,
-}
-
-const WIDTH: i32 = 1920 / 4;
-const HEIGHT: i32 = 1080 / 4;
-
-const WIDTH_F: f32 = 1920.0 / 4.0;
-const HEIGHT_F: f32 = 1080.0 / 4.0;
-
-const MOUSE_LEFT_DOWN: u32 = 1u << 0u;
-const MOUSE_RIGHT_DOWN: u32 = 1u << 1u;
+#include "common.wgsl"
 
 @group(0) @binding(0) var<uniform> info: Info;
 @group(0) @binding(1) var prng: texture_storage_2d<rg32float, read>;
@@ -14,24 +14,25 @@ fn main() {
         *cell = rng.bool() as u32;
     }
 
-    let cells_texture = ctx.create_storage_texture_with_data((W, H), StorageTextureFormat::R32Uint, &initial_cells);
-    let cells_texture_out = ctx.create_storage_texture((W, H), StorageTextureFormat::R32Uint);
+    let cells = PingPong::new(
+        ctx.create_storage_texture_with_data((W, H), StorageTextureFormat::R32Uint, &initial_cells),
+        ctx.create_storage_texture((W, H), StorageTextureFormat::R32Uint),
+    );
     let screen_texture = ctx.create_storage_texture((W, H), StorageTextureFormat::Rgba8Unorm);
 
     let update_pipeline = ctx.create_compute_pipeline(ComputePipelineDescriptor {
-        inputs: &[
-            PipelineInput::StorageTexture(&cells_texture),
-        ],
-        outputs: &[
-            ComputePipelineOutput::StorageTexture(&cells_texture_out),
-            ComputePipelineOutput::StorageTexture(&screen_texture),
-        ],
-        shader: include_str!("update.wgsl").into(),
+        inputs: &[],
+        outputs: &[],
+        bindings: Bindings::new()
+            .bind("cells", (&cells, PingPongSide::Front))
+            .bind("cells_out", (&cells, PingPongSide::Back))
+            .bind("screen", &screen_texture),
+        shader: ShaderSource::Str(include_str!("update.wgsl")),
         shader_entry: "update",
-        dispatch_count: cells_texture.dispatch_count((16, 16)),
+        dispatch_count: cells.a.dispatch_count((16, 16)).into(),
+        ..Default::default()
     });
 
-    let back_copy = ctx.create_texture_copier_fast(&cells_texture_out, &cells_texture);
     let screen_copy = ctx.create_screen_copier(&screen_texture, ScalingType::Nearest);
 
     let mut running = true;
@@ -48,7 +49,7 @@ fn main() {
 
         if running || step {
             ctx.run_compute_pass(encoder, &[&update_pipeline]);
-            ctx.copy_texture_to_texture(encoder, &back_copy);
+            cells.swap();
             if step { running = false; step = false }
         }
         ctx.copy_texture_to_screen(encoder, &screen_copy, output);
@@ -26,23 +26,25 @@ fn main() {
     let path_strip_create = ctx.create_compute_pipeline(ComputePipelineDescriptor {
         inputs: &[PipelineInput::StorageBuffer(&points), PipelineInput::Uniform(&bounds_uniform)],
         outputs: &[ComputePipelineOutput::StorageBuffer(&vertex_buffer)],
-        shader: Shader::String(include_str!("path_create.wgsl")),
+        shader: ShaderSource::Str(include_str!("path_create.wgsl")),
         shader_entry: "path_create",
-        dispatch_count: points.dispatch_count(32),
+        dispatch_count: points.dispatch_count(32).into(),
+        ..Default::default()
     });
 
     let points_create = ctx.create_compute_pipeline(ComputePipelineDescriptor {
         inputs: &[PipelineInput::Uniform(&bounds_uniform)],
         outputs: &[ComputePipelineOutput::StorageBuffer(&points)],
-        shader: Shader::String(include_str!("function.wgsl")),
+        shader: ShaderSource::Str(include_str!("function.wgsl")),
         shader_entry: "points_create",
-        dispatch_count: points.dispatch_count(32),
+        dispatch_count: points.dispatch_count(32).into(),
+        ..Default::default()
     });
 
     let vbuffer = VertexBuffer {
-        vertex_buffer: vertex_buffer.buffer,
+        vertex_buffer: vertex_buffer.buffer.clone(),
         vertex_layout: wgpu::VertexBufferLayout {
-            array_stride: vertex_buffer.layout.size() as _,
+            array_stride: vertex_buffer.raw.layout.size() as _,
             step_mode: wgpu::VertexStepMode::Vertex,
             attributes: &wgpu::vertex_attr_array!(0 => Float32x2),
         },
@@ -54,10 +56,10 @@ fn main() {
     let path_strip_render = ctx.create_render_pipeline(RenderPipelineDescriptor {
         inputs: &[],
         vertex_buffer: &vbuffer,
-        shader: include_str!("path_render.wgsl").into(),
+        shader: ShaderSource::Str(include_str!("path_render.wgsl")),
         shader_vertex_entry: "vertex",
         shader_fragment_entry: "fragment",
-        output_format: OUTPUT_TEXTURE_FORMAT,
+        output_format: ctx.output_texture_format,
     });
 
     #[derive(Copy, Clone)]
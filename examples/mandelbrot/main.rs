@@ -28,7 +28,8 @@ fn main() {
         //shader: ShaderSource::Str(include_str!("shader.wgsl")),
         shader: ShaderSource::File(std::path::Path::new("examples/mandelbrot/shader.wgsl")),
         shader_entry: "render",
-        dispatch_count: texture.dispatch_count((16, 16)),
+        dispatch_count: texture.dispatch_count((16, 16)).into(),
+        ..Default::default()
     });
 
     let screen_copier = ctx.create_screen_copier(&texture, ScalingType::Linear);
@@ -42,6 +43,7 @@ fn main() {
         pub origin_world: [f32; 2],
     }
     let mut translation_state: Option<TranslationState> = None;
+    let mut screenshot: Option<ReadbackTicket<u8>> = None;
 
     // We use run_ex to separate the update loop from the render loop.
     // This allows us to update at a constant 60Hz while render iterations may take longer.
@@ -80,8 +82,14 @@ fn main() {
                 let mut encoder = ctx.device.create_command_encoder(&Default::default());
                 ctx.copy_texture_to_texture(&mut encoder, &screenshot_copier);
                 ctx.queue.submit(std::iter::once(encoder.finish()));
-                screenshot_texture.read_to_png(&ctx, std::path::Path::new("mandelbrot.png"));
+                screenshot = Some(screenshot_texture.read_async(&ctx));
+            }
+
+            // Reading the screenshot back takes a few frames, so check on it each update.
+            if let Some(pixels) = screenshot.as_ref().and_then(|ticket| ticket.poll(&ctx)) {
+                screenshot_texture.write_png(&pixels, std::path::Path::new("mandelbrot.png"));
                 println!("Saved screenshot!");
+                screenshot = None;
             }
 
             if input.just_pressed(Key::KeyQ) { 
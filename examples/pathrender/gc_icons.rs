@@ -1,5 +1,5 @@
 
-#![allow(non_upper_case_globals)]
+#![allow(non_upper_case_globals, dead_code, clippy::excessive_precision, clippy::approx_constant)]
 use crate::PathEl;
 use crate::PathEl::*;
 use glam::f32::Vec2 as Point;
@@ -112,10 +112,10 @@ fn add_quad(
 ) {
     let p01 = p0 - p1;
     let p21 = p2 - p1;
-    for i in 0..64 {
+    for (i, s) in scratch.iter_mut().enumerate() {
         let t = i as f32 / 64.0;
         let tp = 1.0 - t;
-        scratch[i] = p1 + tp*tp*p01 + t*t*p21;
+        *s = p1 + tp*tp*p01 + t*t*p21;
     }
     linearize(scratch, points);
 }
@@ -128,16 +128,22 @@ fn add_cubic(
     p2: Vec2,
     p3: Vec2,
 ) {
-    for i in 0..64 {
+    for (i, s) in scratch.iter_mut().enumerate() {
         let t = i as f32 / 64.0;
         let t2 = t*t;
         let tp = 1.0 - t;
         let tp2 = tp*tp;
-        scratch[i] = tp2*tp*p0 + (3.0*tp2)*(t*p1) + (3.0*tp)*(t2*p2) + t2*t*p3;
+        *s = tp2*tp*p0 + (3.0*tp2)*(t*p1) + (3.0*tp)*(t2*p2) + t2*t*p3;
     }
     linearize(scratch, points);
 }
 
+impl Default for Scene {
+    fn default() -> Scene {
+        Scene::new()
+    }
+}
+
 impl Scene {
     pub fn new() -> Scene {
         Scene {
@@ -162,7 +168,7 @@ impl Scene {
         let point_start = self.points.len();
         self.points.reserve(129);
         for i in 0..=128 {
-            let p = (i as f32) / 128.0 * 2.0 * 3.141592654;
+            let p = (i as f32) / 128.0 * std::f32::consts::TAU;
             let x = p.cos() * radius;
             let y = p.sin() * radius;
             self.points.push(Vec2::new(x, y) + centre);
@@ -343,12 +349,12 @@ fn main() {
         ],
         shader: ShaderSource::File(std::path::Path::new("examples/pathrender/shader.wgsl")),
         shader_entry: "main",
-        dispatch_count: texture.dispatch_count((8, 8)),
+        dispatch_count: texture.dispatch_count((8, 8)).into(),
+        ..Default::default()
     });
     
     let mut timer = ctx.create_timer();
-    let mut n = 0.0;
-    ctx.run((W, H), 60, |encoder, output, delta, input| {
+    ctx.run((W, H), 60, |encoder, output, _delta, input| {
         /*scene.clear();
         n += delta;
         
@@ -380,8 +386,8 @@ fn main() {
             ]
         );*/
         
-        points_buf.update(&ctx, &scene.points.as_slice());
-        shapes_buf.update(&ctx, &scene.shapes.as_slice());
+        points_buf.update(&ctx, scene.points.as_slice());
+        shapes_buf.update(&ctx, scene.shapes.as_slice());
     
         timer.start(encoder);
         ctx.run_compute_pass(encoder, &[&render]);
@@ -57,14 +57,17 @@ fn main() {
     let render_points = ctx.create_render_pipeline_ex(RenderPipelineDescriptorEx {
         inputs: &[PipelineInput::StorageBuffer(&points_buffer), PipelineInput::Uniform(&view_state_uniform)],
         vertex_buffer: Either::B(wgpu::PrimitiveTopology::TriangleStrip),
+        instance_buffer: None,
         shader: ShaderSource::Str(include_str!("shader.wgsl")),
         shader_vertex_entry: "vertex",
         shader_fragment_entry: "fragment",
-        output_format: OUTPUT_TEXTURE_FORMAT,
+        output_format: ctx.output_texture_format,
         blend_state: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+        cull_mode: None,
         draw_range: 0..4,
         instance_range: 0..(SIZE as _),
         disable_depth_test: true,
+        ..Default::default()
     });
 
     ctx.run((W, H), 60, |encoder, output, _delta, keys| {
@@ -15,7 +15,7 @@ fn main() {
             //shader: ShaderSource::File(std::path::Path::new("examples/quat_rot/shader.wgsl")),
             shader_vertex_entry: "vertex",
             shader_fragment_entry: "fragment",
-            output_format: OUTPUT_TEXTURE_FORMAT,
+            output_format: ctx.output_texture_format,
         }.into()
     });
 
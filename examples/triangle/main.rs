@@ -17,7 +17,7 @@ fn main() {
         shader: ShaderSource::Str(include_str!("shader.wgsl")),
         shader_vertex_entry: "vertex",
         shader_fragment_entry: "fragment",
-        output_format: OUTPUT_TEXTURE_FORMAT,
+        output_format: ctx.output_texture_format,
     });
 
     ctx.run((512, 512), 30, |encoder, output, _time_delta, _input| {
@@ -1,30 +1,30 @@
+use ezcompute::*;
+
 fn main() {
-    let size = (16, 16);
-    let mut rgb_buf = vec![0u8; size.0 * size.1 * 4];
-    let mut y_buf = vec![0u8; size.0 * size.1];
-    let mut u_buf = vec![0u8; size.0 * size.1];
-    let mut v_buf = vec![0u8; size.0 * size.1];
+    let ctx = Ctx::new();
+
+    let size = (256, 256);
+    let frame_count = 120;
+    let texture = ctx.create_texture(size, wgpu::TextureFormat::Rgba8Unorm);
 
-    convert_rgba_to_yuv420p(&rgb_buf, size.0, size.1, &mut y_buf, &mut u_buf, &mut v_buf);
+    let mut recorder = Recorder::new(
+        std::path::Path::new("video.y4m"),
+        size,
+        RecordingFormat::Y4m { chroma: Y4mChroma::C420, frame_rate: 60 },
+    ).unwrap();
 
-    let frame_rate = 60.0;
-    let frame_count = 60;
-    let file = std::fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .open(std::path::Path::new("video.mkv")).expect("could not open or create file");
-    let mut encoder = y4m::encode(size.0, size.1, y4m::Ratio::new(60, 1))
-        .with_colorspace(y4m::Colorspace::C444)
-        .write_header(file)
-        .unwrap();
+    let mut texels = vec![[0u8; 4]; (size.0 * size.1) as usize];
+    for frame_num in 0..frame_count {
+        for (i, texel) in texels.iter_mut().enumerate() {
+            let x = i as u32 % size.0;
+            let y = i as u32 / size.0;
+            *texel = [(x + frame_num * 2) as u8, y as u8, (frame_num * 2) as u8, 255];
+        }
+        texture.update(&ctx, &texels);
 
-    let mut frame_num = 0;
-    loop {
-        let frame = y4m::Frame::new([&y_buf, &u_buf, &v_buf], None);
-        if frame_num == frame_count { break }
-        frame_num += 1;
-        print!("encoding frame {}/{}\r", frame_num, frame_count);
-        encoder.write_frame(&frame).unwrap();
+        print!("encoding frame {}/{}\r", frame_num+1, frame_count);
+        recorder.write_texture(&ctx, &texture).unwrap();
     }
-}
 
+    recorder.finish().unwrap();
+}
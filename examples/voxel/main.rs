@@ -133,7 +133,7 @@ impl ChunkDataRef {
 }
 
 impl ChunkData {
-    pub fn layer_masks(&self, range: ChunkDataRef) -> ChunkLayerMaskRange {
+    pub fn layer_masks(&self, range: ChunkDataRef) -> ChunkLayerMaskRange<'_> {
         let data_range = range.data_range();
         let layers = &self.layer_masks[data_range];
         ChunkLayerMaskRange {
@@ -142,7 +142,7 @@ impl ChunkData {
         }
     }
 
-    pub fn layers(&self, range: ChunkDataRef) -> ChunkLayerRange {
+    pub fn layers(&self, range: ChunkDataRef) -> ChunkLayerRange<'_> {
         let data_range = range.data_range();
         let layers = &self.layers[data_range];
         ChunkLayerRange {
@@ -164,8 +164,8 @@ impl ChunkData {
                 let row_slice = &layer[(z << 5)..][..32];
 
                 let mut mask = 0u32;
-                for x in 0..32 {
-                    if row_slice[x] != 0 {
+                for (x, &voxel) in row_slice.iter().enumerate() {
+                    if voxel != 0 {
                         mask ^= 1 << x;
                     }
                 }
@@ -185,8 +185,7 @@ const fn pack_chunk_pos(x: usize, y: usize, z: usize) -> u16 {
 }
 
 fn find_chunk(chunk_refs: &[ChunkRef], offset: IVec3) -> Option<ChunkDataRef> {
-    for i in 0..chunk_refs.len() {
-        let pos = chunk_refs[i];
+    for pos in chunk_refs {
         if pos.offset == offset {
             return Some(pos.data_ref)
         }
@@ -207,6 +206,12 @@ pub struct Chunks {
     pub refs:  Vec<ChunkRef>,
 }
 
+impl Default for Chunks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Chunks {
     pub fn new() -> Self {
         Chunks {
@@ -310,8 +315,8 @@ impl Chunks {
                             let kj = (k+j) as usize;
                             let ku = k as usize;
                             let t = (mat[ku] ^ (mat[kj] >> j)) & m; 
-                            mat[ku] = mat[ku] ^ t; 
-                            mat[kj] = mat[kj] ^ (t << j); 
+                            mat[ku] ^= t; 
+                            mat[kj] ^= t << j; 
 
                             k = (k + j + 1) & !j;
                         }
@@ -457,7 +462,7 @@ fn main() {
         angle: 0.0,
     };
 
-    const VOXEL_COLOURS: &'static [[f32; 4]] = &[
+    const VOXEL_COLOURS: &[[f32; 4]] = &[
         [0.5, 0.2, 0.2, 1.0],
         [0.2, 0.5, 0.2, 1.0],
         [0.2, 0.2, 0.5, 1.0],
@@ -491,6 +496,7 @@ fn main() {
     let ctx = Ctx::new();
 
     let camera_uniform = ctx.create_uniform(&camera.to_matrix());
+    let camera_group = ctx.create_bind_group(&[PipelineInput::Uniform(&camera_uniform)]);
     let loaded_chunk_buffer = ctx.create_storage_buffer(&chunks.refs);
     let surfaces = chunks.calculate_surfaces();
     let surface_buffer = ctx.create_storage_buffer(&surfaces);
@@ -498,23 +504,24 @@ fn main() {
 
     let surface_render = ctx.create_render_pipeline_ex(RenderPipelineDescriptorEx {
         inputs: &[
-            PipelineInput::Uniform(&camera_uniform),
             PipelineInput::StorageBuffer(&loaded_chunk_buffer),
             PipelineInput::StorageBuffer(&surface_buffer),
             PipelineInput::StorageBuffer(&voxel_colour_buffer),
         ],
         vertex_buffer: Either::B(wgpu::PrimitiveTopology::TriangleStrip),
         instance_buffer: None,
+        groups: &[PipelineGroup::Shared(&camera_group)],
         shader: ShaderSource::File(std::path::Path::new("examples/voxel/shader.wgsl")),
         shader_vertex_entry: "vertex",
         shader_fragment_entry: "fragment",
-        output_format: OUTPUT_TEXTURE_FORMAT,
+        output_format: ctx.output_texture_format,
         blend_state: None,
         cull_mode: Some(wgpu::Face::Back),
         draw_range: 0..4,
         instance_range: 0..surface_buffer.len(),
 
         disable_depth_test: false,
+        ..Default::default()
     });
 
     let mut timer = ctx.create_timer();
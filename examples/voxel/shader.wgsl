@@ -1,7 +1,7 @@
-@group(0) @binding(0) var<uniform> camera: mat4x4<f32>;
-@group(0) @binding(1) var<storage, read> chunks: array<vec4<i32>>;
-@group(0) @binding(2) var<storage, read> surfaces: array<vec2<u32>>;
-@group(0) @binding(3) var<storage, read> colours: array<vec4<f32>>;
+@group(0) @binding(0) var<storage, read> chunks: array<vec4<i32>>;
+@group(0) @binding(1) var<storage, read> surfaces: array<vec2<u32>>;
+@group(0) @binding(2) var<storage, read> colours: array<vec4<f32>>;
+@group(1) @binding(0) var<uniform> camera: mat4x4<f32>;
 
 struct VertexOutput {
     @builtin(position) pos: vec4<f32>,
@@ -25,22 +25,171 @@ pub struct Ctx {
 
     pub alloc: &'static bumpalo::Bump,
 
+    /// The features and limits that were granted by the adapter.
+    pub features: wgpu::Features,
+    pub limits: wgpu::Limits,
+
+    /// Virtual files for shader `#include`s. Checked before the filesystem.
+    pub shader_includes: std::cell::RefCell<std::collections::HashMap<&'static str, &'static str>>,
+
+    /// Recycled staging buffers for buffer updates and readbacks.
+    pub staging: StagingPool,
+
     #[cfg(feature = "vello")]
     pub vello_renderer: std::cell::RefCell<vello::Renderer>,
 }
 
 pub struct CtxDescriptor {
     pub srgb_output_format: bool,
+
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    /// Use a software adapter such as lavapipe or llvmpipe.
+    pub force_fallback_adapter: bool,
+    /// Only use an adapter whose name contains this string (case insensitive).
+    /// `force_fallback_adapter` still applies, and `power_preference` picks between several matches.
+    /// `Ctx::enumerate_adapters` lists the available names.
+    pub adapter_name: Option<String>,
+
+    /// Device creation fails if any of these are unsupported.
+    pub required_features: wgpu::Features,
+    /// Requested only if the adapter supports them. Check `Ctx::features` for what was granted.
+    pub optional_features: wgpu::Features,
+    /// Device creation fails if these are not supported.
+    pub required_limits: wgpu::Limits,
+    /// Requested instead of `required_limits` if the adapter supports them.
+    pub optional_limits: wgpu::Limits,
 }
 
 impl Default for CtxDescriptor {
     fn default() -> CtxDescriptor {
         CtxDescriptor {
             srgb_output_format: true,
+            backends: wgpu::Backends::PRIMARY,
+            power_preference: wgpu::PowerPreference::None,
+            force_fallback_adapter: false,
+            adapter_name: None,
+            required_features: wgpu::Features::empty(),
+            optional_features: wgpu::Features::CLEAR_TEXTURE
+                | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                | wgpu::Features::TEXTURE_BINDING_ARRAY
+                | wgpu::Features::TIMESTAMP_QUERY
+                | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS
+                | wgpu::Features::PUSH_CONSTANTS,
+            required_limits: wgpu::Limits::downlevel_defaults(),
+            optional_limits: wgpu::Limits::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CtxError {
+    /// No adapter matching the requested options was found.
+    NoAdapter,
+    /// No adapter matches `CtxDescriptor::adapter_name` and `force_fallback_adapter`.
+    AdapterNotFound {
+        name: String,
+        available: Vec<wgpu::AdapterInfo>,
+    },
+    /// The adapter does not support some of the required features.
+    MissingFeatures(wgpu::Features),
+    /// The adapter does not support the required limits.
+    MissingLimits,
+    RequestDevice(wgpu::RequestDeviceError),
+    /// The queue reports a timestamp period of zero.
+    TimestampsUnsupported,
+    ShaderRead {
+        path: &'static std::path::Path,
+        error: std::io::Error,
+    },
+    /// A shader failed to preprocess or compile, or its pipeline failed to validate.
+    Shader(ShaderError),
+    /// A pipeline's descriptor or resources don't fit together, e.g. a rebind with different binding types.
+    Pipeline(String),
+    Io(std::io::Error),
+    /// Encoding or writing a video or image failed.
+    Encode(String),
+    /// Decoding an image failed.
+    Decode(String),
+    /// The texture format can't be used for this, e.g. loading a png into a depth format.
+    UnsupportedFormat(wgpu::TextureFormat),
+}
+
+impl std::fmt::Display for CtxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CtxError::NoAdapter => write!(f, "no suitable adapter found"),
+            CtxError::AdapterNotFound { name, available } => {
+                write!(f, "no adapter matching \"{}\" found, available:", name)?;
+                if available.is_empty() { write!(f, " none")?; }
+                for info in available {
+                    write!(f, "\n  {} ({:?}, {:?})", info.name, info.device_type, info.backend)?;
+                }
+                Ok(())
+            }
+            CtxError::MissingFeatures(features) => write!(f, "adapter is missing required features: {:?}", features),
+            CtxError::MissingLimits => write!(f, "adapter does not support the required limits"),
+            CtxError::RequestDevice(e) => write!(f, "device request failed: {}", e),
+            CtxError::TimestampsUnsupported => write!(f, "timestamps are unsupported on this machine"),
+            CtxError::ShaderRead { path, error } => write!(f, "could not open shader {}: {}", path.display(), error),
+            CtxError::Shader(e) => write!(f, "{}", e),
+            CtxError::Pipeline(e) => write!(f, "{}", e),
+            CtxError::Io(e) => write!(f, "io error: {}", e),
+            CtxError::Encode(e) => write!(f, "encoding failed: {}", e),
+            CtxError::Decode(e) => write!(f, "decoding failed: {}", e),
+            CtxError::UnsupportedFormat(format) => write!(f, "unsupported texture format {:?}", format),
+        }
+    }
+}
+
+impl std::error::Error for CtxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CtxError::RequestDevice(e) => Some(e),
+            CtxError::ShaderRead { error, .. } => Some(error),
+            CtxError::Shader(e) => Some(e),
+            CtxError::Io(e) => Some(e),
+            _ => None,
         }
     }
 }
 
+impl From<wgpu::RequestDeviceError> for CtxError {
+    fn from(e: wgpu::RequestDeviceError) -> Self {
+        CtxError::RequestDevice(e)
+    }
+}
+
+impl From<ShaderError> for CtxError {
+    fn from(e: ShaderError) -> Self {
+        CtxError::Shader(e)
+    }
+}
+
+impl From<std::io::Error> for CtxError {
+    fn from(e: std::io::Error) -> Self {
+        CtxError::Io(e)
+    }
+}
+
+/// Lower ranks are preferred. The first of several equally ranked adapters is used.
+fn adapter_rank(device_type: wgpu::DeviceType, power_preference: wgpu::PowerPreference) -> u32 {
+    match (power_preference, device_type) {
+        (wgpu::PowerPreference::None, _) => 0,
+        (wgpu::PowerPreference::HighPerformance, wgpu::DeviceType::DiscreteGpu) => 0,
+        (wgpu::PowerPreference::HighPerformance, wgpu::DeviceType::IntegratedGpu) => 1,
+        (wgpu::PowerPreference::LowPower, wgpu::DeviceType::IntegratedGpu) => 0,
+        (wgpu::PowerPreference::LowPower, wgpu::DeviceType::DiscreteGpu) => 1,
+        _ => 2,
+    }
+}
+
+impl Default for Ctx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Ctx {
     pub fn new() -> Self {
         Self::new_ex(CtxDescriptor::default())
@@ -51,31 +200,67 @@ impl Ctx {
     }
 
     pub async fn new_ex_async(desc: CtxDescriptor) -> Self {
-        env_logger::init();
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
-            dx12_shader_compiler: Default::default(),
-            gles_minor_version: Default::default(),
-            flags: Default::default(),
-        });
-        let adapter = instance.request_adapter(&Default::default()).await.unwrap();
+        match Self::try_new_ex_async(desc).await {
+            Ok(ctx) => ctx,
+            Err(e) => panic!("Ctx::new: {}", e),
+        }
+    }
+
+    pub fn try_new() -> Result<Self, CtxError> {
+        Self::try_new_ex(CtxDescriptor::default())
+    }
+
+    pub fn try_new_ex(desc: CtxDescriptor) -> Result<Self, CtxError> {
+        pollster::block_on(Self::try_new_ex_async(desc))
+    }
+
+    pub async fn try_new_ex_async(desc: CtxDescriptor) -> Result<Self, CtxError> {
+        // fails if a logger is already set, e.g. by a previous Ctx
+        let _ = env_logger::try_init();
+        let instance = Self::create_instance(desc.backends);
+        let adapter = match desc.adapter_name {
+            Some(ref name) => Self::find_adapter(&instance, &desc, name)?,
+            None => instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: desc.power_preference,
+                force_fallback_adapter: desc.force_fallback_adapter,
+                compatible_surface: None,
+            }).await.ok_or(CtxError::NoAdapter)?,
+        };
+
+        let required_features = desc.required_features
+            | if DEBUG_LINES {
+                wgpu::Features::POLYGON_MODE_LINE
+            } else {
+                wgpu::Features::empty()
+            };
+
+        let adapter_features = adapter.features();
+        let missing_features = required_features - adapter_features;
+        if !missing_features.is_empty() {
+            return Err(CtxError::MissingFeatures(missing_features));
+        }
+        let features = required_features | (desc.optional_features & adapter_features);
+
+        let adapter_limits = adapter.limits();
+        let mut limits = if desc.optional_limits.check_limits(&adapter_limits) {
+            desc.optional_limits
+        } else if desc.required_limits.check_limits(&adapter_limits) {
+            desc.required_limits
+        } else {
+            return Err(CtxError::MissingLimits);
+        };
+        if features.contains(wgpu::Features::PUSH_CONSTANTS) {
+            limits.max_push_constant_size = limits.max_push_constant_size.max(adapter_limits.max_push_constant_size);
+        }
+
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
                 label: None,
-                required_features: wgpu::Features::CLEAR_TEXTURE
-                    | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
-                    | wgpu::Features::TEXTURE_BINDING_ARRAY
-                    | wgpu::Features::TIMESTAMP_QUERY
-                    | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS
-                    | if DEBUG_LINES {
-                        wgpu::Features::POLYGON_MODE_LINE
-                    } else {
-                        wgpu::Features::empty()
-                    },
-                required_limits: Default::default(),
+                required_features: features,
+                required_limits: limits.clone(),
             },
             None
-        ).await.unwrap();
+        ).await?;
 
         let output_texture_format = if desc.srgb_output_format {
             wgpu::TextureFormat::Bgra8UnormSrgb
@@ -132,15 +317,53 @@ impl Ctx {
             num_init_threads: None,
         }).unwrap());
 
-        Self {
+        Ok(Self {
             device, queue, instance,
             output_texture_format,
             copy_pipeline_layout, copy_bind_group_layout, copy_sampler_linear, copy_sampler_nearest, copy_shader,
             alloc: Box::leak(Box::new(bumpalo::Bump::new())),
+            features,
+            limits,
+            shader_includes: Default::default(),
+            staging: Default::default(),
 
             #[cfg(feature = "vello")]
             vello_renderer,
-        }
+        })
+    }
+
+    /// Lists the adapters available on the given backends.
+    pub fn enumerate_adapters(backends: wgpu::Backends) -> Vec<wgpu::AdapterInfo> {
+        Self::create_instance(backends)
+            .enumerate_adapters(backends)
+            .iter()
+            .map(wgpu::Adapter::get_info)
+            .collect()
+    }
+
+    /// Picks the adapter `power_preference` prefers among those whose name contains `name`.
+    /// Only software adapters match if `force_fallback_adapter` is set.
+    fn find_adapter(instance: &wgpu::Instance, desc: &CtxDescriptor, name: &str) -> Result<wgpu::Adapter, CtxError> {
+        let adapters = instance.enumerate_adapters(desc.backends);
+        let available: Vec<wgpu::AdapterInfo> = adapters.iter().map(wgpu::Adapter::get_info).collect();
+
+        let lowercase_name = name.to_lowercase();
+        let adapter = adapters.into_iter()
+            .zip(&available)
+            .filter(|(_, info)| info.name.to_lowercase().contains(&lowercase_name))
+            .filter(|(_, info)| !desc.force_fallback_adapter || info.device_type == wgpu::DeviceType::Cpu)
+            .min_by_key(|(_, info)| adapter_rank(info.device_type, desc.power_preference))
+            .map(|(adapter, _)| adapter);
+        adapter.ok_or(CtxError::AdapterNotFound { name: name.to_string(), available })
+    }
+
+    fn create_instance(backends: wgpu::Backends) -> wgpu::Instance {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends,
+            dx12_shader_compiler: Default::default(),
+            gles_minor_version: Default::default(),
+            flags: Default::default(),
+        })
     }
 
     #[cfg(feature = "winit")]
@@ -182,6 +405,54 @@ impl Ctx {
         )
     }
 
+    /// Same as `run`, but renders `frame_count` frames to an offscreen `RenderTexture` instead of a window.
+    /// Useful for servers and tests, and available without the `winit` feature.
+    pub fn run_headless<F>(
+        &self,
+        size: (u32, u32),
+        frame_count: u32,
+        f: F,
+    ) where
+        F: FnMut(&mut wgpu::CommandEncoder, &RenderTexture, f32, Input) -> Option<WindowTask>,
+    {
+        self.run_headless_ex(size, frame_count, 60, None, f)
+    }
+
+    /// Each frame is passed a delta of `1 / frames_per_second`.
+    /// If `frame_sender` is passed, every rendered frame is read back with `RenderTexture::read`.
+    pub fn run_headless_ex<F>(
+        &self,
+        size: (u32, u32),
+        frame_count: u32,
+        frames_per_second: u32,
+        frame_sender: Option<std::sync::mpsc::Sender<Vec<u8>>>,
+        mut f: F,
+    ) where
+        F: FnMut(&mut wgpu::CommandEncoder, &RenderTexture, f32, Input) -> Option<WindowTask>,
+    {
+        let output = self.create_render_texture(size, self.output_texture_format);
+        let delta = (frames_per_second as f32).recip();
+
+        for _ in 0..frame_count {
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            let input = Input { 
+                key_events: &[], 
+                mouse_position: None, 
+                mouse_scroll: 0.0, 
+                mouse_buttons: MouseButtons { left: None, middle: None, right: None },
+            };
+            let task = (f)(&mut encoder, &output, delta, input);
+            self.queue.submit(std::iter::once(encoder.finish()));
+
+            if let Some(ref sender) = frame_sender {
+                start_texture_read(self, &output.texture, sender.clone());
+                self.device.poll(wgpu::Maintain::Wait);
+            }
+
+            if task == Some(WindowTask::Exit) { break }
+        }
+    }
+
     #[cfg(feature = "winit")]
     pub fn run_ex<F>(
         &self,
@@ -225,7 +496,7 @@ impl Ctx {
 
         enum StateMaybe<'a, F> {
             Uninit(PreInitState<'a, F>),
-            Init(State<'a, F>),
+            Init(Box<State<'a, F>>),
         }
 
         impl<'a, F> State<'a, F> {
@@ -259,7 +530,7 @@ impl Ctx {
                     sample_count: 1,
                     dimension: wgpu::TextureDimension::D2,
                     format: wgpu::TextureFormat::Depth32Float,
-                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
                     view_formats: &[],
                 };
 
@@ -305,7 +576,7 @@ impl Ctx {
                 take_mut::take(self, |s| {
                     match s {
                         StateMaybe::Uninit(init) => {
-                            StateMaybe::Init(State::init(init, event_loop))
+                            StateMaybe::Init(Box::new(State::init(init, event_loop)))
                         }
                         _ => s,
                     }
@@ -381,7 +652,7 @@ impl Ctx {
                                 sample_count: 1,
                                 dimension: wgpu::TextureDimension::D2,
                                 format: wgpu::TextureFormat::Depth32Float,
-                                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
                                 view_formats: &[],
                             });
                             st.output.depth_view = st.output.depth_texture.create_view(&Default::default())
@@ -414,13 +685,10 @@ impl Ctx {
                     winit::event::WindowEvent::CursorLeft { .. } => {
                         st.mouse_position = None;
                     },
-                    winit::event::WindowEvent::MouseWheel { delta, .. } => {
-                        match delta {
-                            winit::event::MouseScrollDelta::LineDelta(_, y) => {
-                                st.mouse_scroll += y;
-                            }
-                            _ => (),
-                        }
+                    winit::event::WindowEvent::MouseWheel { 
+                        delta: winit::event::MouseScrollDelta::LineDelta(_, y), .. 
+                    } => {
+                        st.mouse_scroll += y;
                     },
                     winit::event::WindowEvent::MouseInput { 
                         state: winit::event::ElementState::Pressed, button, .. 
@@ -524,94 +792,154 @@ impl Ctx {
         ).unwrap();
     }
 
-    // #[cfg(feature = "video")]
-    //pub fn record<F>(
-    //    &self, 
-    //    output: &'static str, 
-    //    size: (u32, u32), 
-    //    frame_count: u32, 
-    //    frame_rate: u32,
-    //    mut f: F
-    //) where
-    //    F: FnMut(&Texture)
-    //{
-    //    let texture = self.create_texture(size, wgpu::TextureFormat::Bgra8UnormSrgb);
-    //    let size = (size.0 as usize, size.1 as usize);
-    //    let size_i = (size.0 as i32, size.1 as i32);
-
-    //    let (sender, receiver) = std::sync::mpsc::channel::<Vec<u8>>();
-
-    //    let write_thread = std::thread::spawn(move || {
-    //        let mut file = std::io::BufWriter::new(std::fs::OpenOptions::new()
-    //            .create(true)
-    //            .write(true)
-    //            .open(std::path::Path::new(output))
-    //            .expect("could not open or create file"));
-
-    //        let mut encoder = x264::Setup::high()
-    //            .timebase(1, 90_000)
-    //            .fps(frame_rate, 1)
-    //            .annexb(false)
-    //            .build(x264::Colorspace::BGRA, size_i.0, size_i.1)
-    //            .unwrap();
-
-    //        let mut mp4_writer = mp4::Mp4Writer::write_start(file, &Mp4Config {
-    //            major_brand: str::parse("mp42").unwrap(),
-    //            minor_version: 0,
-    //            compatible_brands: vec![],
-    //            timescale: 90_000,
-    //        });
-    //        
-    //        mp4_writer.add_track(&mp4::TrackConfig {
-    //            track_type: mpv::TrackType::Video,
-    //            timescale: 90_000,
-    //            language: String::new(),
-    //            media_conf: mp4::MediaConfig::AvcConfig(mp4::AvcConfig {
-    //                width: size.0 as _,
-    //                height: size.1 as _,
-
-    //            })
-    //        });
-
-    //        use std::io::Write;
-    //        file.write_all(encoder.headers().unwrap().entirety()).unwrap();
-
-    //        let mut frame_num: i64 = 0;
-    //        let frame_rate = frame_rate as i64;
-    //        loop {
-    //            let rgba_buf = match receiver.recv() {
-    //                Ok(buf) => buf,
-    //                Err(_) => break
-    //            };
-
-    //            // 90kHz resolution
-    //            let timestamp = frame_num * 90_000 / frame_rate;
-    //            //let timestamp = frame_num * 60;
-
-    //            let image = x264::Image::bgra(size_i.0, size_i.1, &rgba_buf);
-    //            let (data, _) = encoder.encode(timestamp, image).unwrap();
-    //            file.write_all(data.entirety()).unwrap();
-    //            frame_num += 1;
-    //        }
-
-    //        let mut flush = encoder.flush();
-    //        while let Some(result) = flush.next() {
-    //            let (data, _) = result.unwrap();
-    //            file.write_all(data.entirety()).unwrap();
-    //        }
-
-    //        file.flush().unwrap();
-    //    });
-
-    //    for _ in 0..frame_count {
-    //        (f)(&texture);
-    //        texture.read(self, sender.clone());
-    //    }
-    //    std::mem::drop(sender);
-
-    //    write_thread.join().unwrap();
-    //}
+    /// Renders `frame_count` frames and encodes them as H.264 into an mp4 file at `output`.
+    /// `f` is called once per frame, like the closure passed to `run`.
+    /// The width and height of `size` must be even.
+    #[cfg(feature = "video")]
+    pub fn record<F>(
+        &self, 
+        output: &std::path::Path, 
+        size: (u32, u32), 
+        frame_count: u32, 
+        frame_rate: u32,
+        mut f: F
+    ) -> Result<(), CtxError> where
+        F: FnMut(&mut wgpu::CommandEncoder, &RenderTexture)
+    {
+        if size.0 == 0 || size.1 == 0 || size.0 & 1 != 0 || size.1 & 1 != 0 {
+            return Err(CtxError::Encode(format!("cannot record frames of size {}x{}, the width and height must be even", size.0, size.1)));
+        }
+        if frame_rate == 0 {
+            return Err(CtxError::Encode(String::from("frame_rate cannot be zero")));
+        }
+
+        let file = std::io::BufWriter::new(std::fs::File::create(output)?);
+        let texture = self.create_render_texture(size, self.output_texture_format);
+
+        let (sender, receiver) = std::sync::mpsc::channel::<Vec<u8>>();
+
+        let write_thread = std::thread::spawn(move || -> Result<(), CtxError> {
+            let encode_error = |_| CtxError::Encode(String::from("x264 encoding failed"));
+            let mux_error = |e: mp4::Error| CtxError::Encode(e.to_string());
+
+            let (width, height) = (size.0 as usize, size.1 as usize);
+            let mut encoder = x264::Setup::preset(x264::Preset::Medium, x264::Tune::None, false, true)
+                .fps(frame_rate, 1)
+                .timebase(1, frame_rate)
+                .annexb(false)
+                .high()
+                .build(x264::Colorspace::I420, width as i32, height as i32)
+                .map_err(encode_error)?;
+
+            // Without annex b, every nal unit is prefixed with its 4 byte length.
+            let mut seq_param_set = Vec::new();
+            let mut pic_param_set = Vec::new();
+            let headers = encoder.headers().map_err(encode_error)?;
+            for i in 0..headers.len() {
+                let unit = headers.unit(i);
+                let nal = &unit.as_ref()[4..];
+                match nal[0] & 0x1F {
+                    7 => seq_param_set = nal.to_vec(),
+                    8 => pic_param_set = nal.to_vec(),
+                    _ => (),
+                }
+            }
+
+            let mut mp4_writer = mp4::Mp4Writer::write_start(file, &mp4::Mp4Config {
+                major_brand: str::parse("isom").unwrap(),
+                minor_version: 512,
+                compatible_brands: vec![
+                    str::parse("isom").unwrap(),
+                    str::parse("iso2").unwrap(),
+                    str::parse("avc1").unwrap(),
+                    str::parse("mp41").unwrap(),
+                ],
+                timescale: 1000,
+            }).map_err(mux_error)?;
+
+            mp4_writer.add_track(&mp4::TrackConfig {
+                track_type: mp4::TrackType::Video,
+                timescale: frame_rate,
+                language: String::from("und"),
+                media_conf: mp4::MediaConfig::AvcConfig(mp4::AvcConfig {
+                    width: width as u16,
+                    height: height as u16,
+                    seq_param_set,
+                    pic_param_set,
+                }),
+            }).map_err(mux_error)?;
+
+            let track_id = 1;
+            let sample = |data: x264::Data, picture: x264::Picture| mp4::Mp4Sample {
+                start_time: picture.dts() as u64,
+                duration: 1,
+                rendering_offset: (picture.pts() - picture.dts()) as i32,
+                is_sync: picture.keyframe(),
+                bytes: bytes::Bytes::copy_from_slice(data.entirety()),
+            };
+
+            let mut y = vec![0u8; width * height];
+            let mut u = vec![0u8; width * height / 4];
+            let mut v = vec![0u8; width * height / 4];
+
+            let mut frame_num: i64 = 0;
+            while let Ok(bgra) = receiver.recv() {
+                rgba_to_yuv420(&bgra, true, width, height, &mut y, &mut u, &mut v);
+                let image = x264::Image::new(x264::Colorspace::I420, width as i32, height as i32, &[
+                    x264::Plane { stride: width as i32, data: &y },
+                    x264::Plane { stride: width as i32 / 2, data: &u },
+                    x264::Plane { stride: width as i32 / 2, data: &v },
+                ]);
+
+                let (data, picture) = encoder.encode(frame_num, image).map_err(encode_error)?;
+                if !data.entirety().is_empty() {
+                    mp4_writer.write_sample(track_id, &sample(data, picture)).map_err(mux_error)?;
+                }
+                frame_num += 1;
+            }
+
+            let mut flush = encoder.flush();
+            while let Some(result) = flush.next() {
+                let (data, picture) = result.map_err(encode_error)?;
+                mp4_writer.write_sample(track_id, &sample(data, picture)).map_err(mux_error)?;
+            }
+
+            mp4_writer.write_end().map_err(mux_error)?;
+            std::io::Write::flush(&mut mp4_writer.into_writer())?;
+            Ok(())
+        });
+
+        let (frame_sender, frame_receiver) = std::sync::mpsc::channel::<Vec<u8>>();
+        let mut read_error = None;
+        for _ in 0..frame_count {
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            (f)(&mut encoder, &texture);
+            self.queue.submit(std::iter::once(encoder.finish()));
+            start_texture_read(self, &texture.texture, frame_sender.clone());
+            self.device.poll(wgpu::Maintain::Wait);
+
+            let Ok(frame) = frame_receiver.try_recv() else {
+                read_error = Some(CtxError::Encode(String::from("frame readback failed")));
+                break;
+            };
+            // The writer thread only hangs up after failing, so its error is returned below.
+            if sender.send(frame).is_err() {
+                break;
+            }
+        }
+        std::mem::drop(sender);
+
+        let result = match write_thread.join() {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e),
+        };
+        match read_error {
+            Some(e) => result.and(Err(e)),
+            None => result,
+        }
+    }
 
+    /// Pipelines keep their samplers to rebuild bind groups, so bind one from `create_shared_sampler` instead.
     pub fn create_sampler(&self, oob: wgpu::AddressMode, filter: wgpu::FilterMode) -> wgpu::Sampler {
         self.device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: oob,
@@ -623,6 +951,11 @@ impl Ctx {
         })
     }
 
+    /// A sampler for `PipelineInput::Sampler` and `PipelineResource::Sampler`.
+    pub fn create_shared_sampler(&self, oob: wgpu::AddressMode, filter: wgpu::FilterMode) -> std::sync::Arc<wgpu::Sampler> {
+        std::sync::Arc::new(self.create_sampler(oob, filter))
+    }
+
     pub fn create_instance_buffer<T: bytemuck::NoUninit>(
         &self, 
         desc: InstanceBufferDescriptor<'_, T>,
@@ -697,7 +1030,7 @@ impl Ctx {
         let vertex_count = desc.vertices.len() as u32;
 
         VertexBuffer { 
-            vertex_buffer, 
+            vertex_buffer: SharedBuffer::new(vertex_buffer, vertex_count), 
             vertex_layout, 
             vertex_count,
             index_buffer,
@@ -705,57 +1038,84 @@ impl Ctx {
         }
     }
 
-    pub fn create_storage_buffer<T: bytemuck::NoUninit>(&self, data: &[T]) -> StorageBuffer {
+    pub fn create_storage_buffer<T: bytemuck::NoUninit>(&self, data: &[T]) -> StorageBuffer<T> {
         self.create_storage_buffer_ex::<T>(Either::A(data), wgpu::BufferUsages::empty())
     }
 
-    pub fn create_storage_buffer_empty<T: bytemuck::NoUninit>(&self, len: usize) -> StorageBuffer {
+    pub fn create_storage_buffer_empty<T: bytemuck::NoUninit>(&self, len: usize) -> StorageBuffer<T> {
         self.create_storage_buffer_ex::<T>(Either::B(len), wgpu::BufferUsages::empty())
     }
 
+    /// `[x, y, z]` workgroup counts for `DispatchCount::Indirect`, which compute shaders can write to.
+    pub fn create_dispatch_indirect_buffer(&self, counts: &[[u32; 3]]) -> StorageBuffer<[u32; 3]> {
+        self.create_storage_buffer_ex(Either::A(counts), wgpu::BufferUsages::INDIRECT)
+    }
+
+    /// `[vertex_count, instance_count, first_vertex, first_instance]` for `draw_indirect`.
+    pub fn create_draw_indirect_buffer(&self, args: &[[u32; 4]]) -> StorageBuffer<[u32; 4]> {
+        self.create_storage_buffer_ex(Either::A(args), wgpu::BufferUsages::INDIRECT)
+    }
+
+    /// `[index_count, instance_count, first_index, base_vertex, first_instance]` for `draw_indirect`
+    /// with an indexed vertex buffer. `base_vertex` is an `i32`.
+    pub fn create_draw_indexed_indirect_buffer(&self, args: &[[u32; 5]]) -> StorageBuffer<[u32; 5]> {
+        self.create_storage_buffer_ex(Either::A(args), wgpu::BufferUsages::INDIRECT)
+    }
+
+    /// Empty buffers still get room for one element, since bindings cannot be empty.
+    /// The size of `T` must be a multiple of 4 bytes, so that elements can be written in place.
     pub fn create_storage_buffer_ex<T: bytemuck::NoUninit>(
         &self, 
         data_or_size: Either<&[T], usize>,
         extra_usages: wgpu::BufferUsages,
-    ) -> StorageBuffer {
+    ) -> StorageBuffer<T> {
         if std::alloc::Layout::new::<T>().size() == 0 { panic!("buffer size cannot be zero") }
-
+        assert!(
+            std::mem::size_of::<T>().is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT as usize),
+            "Ctx::create_storage_buffer_ex: element size {} must be a multiple of {}",
+            std::mem::size_of::<T>(), wgpu::COPY_BUFFER_ALIGNMENT
+        );
 
         let layout = std::alloc::Layout::new::<T>();
         let usage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC 
             | wgpu::BufferUsages::COPY_DST | extra_usages;
 
-        let buffer = match data_or_size {
-            Either::A(data) => {
-                if data.len() == 0 { panic!("buffer size cannot be zero") }
-                self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        let (buffer, len) = match data_or_size {
+            Either::A(data) if !data.is_empty() => {
+                let buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: None,
                     contents: bytemuck::cast_slice(data),
                     usage,
-                })
+                });
+                (buffer, data.len())
             },
-            Either::B(len) => {
-                if len == 0 { panic!("buffer size cannot be zero") }
-                self.device.create_buffer(&wgpu::BufferDescriptor {
-                    label: None,
-                    size: len as u64 * layout.size() as u64,
-                    usage,
-                    mapped_at_creation: false,
-                })
-            }
+            Either::A(_) => (self.create_buffer_with_capacity(0, layout, usage), 0),
+            Either::B(len) => (self.create_buffer_with_capacity(len as u32, layout, usage), len),
         };
 
-        StorageBuffer { buffer, layout }
+        let raw = RawStorageBuffer { buffer: SharedBuffer::new(buffer, len as u32), layout };
+        StorageBuffer { raw, _type: std::marker::PhantomData }
+    }
+
+    /// Room for at least one element, padded to the copy alignment.
+    fn create_buffer_with_capacity(&self, capacity: u32, layout: std::alloc::Layout, usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        let size = capacity.max(1) as u64 * layout.pad_to_align().size() as u64;
+        self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: size.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT),
+            usage,
+            mapped_at_creation: false,
+        })
     }
 
-    pub fn create_uniform<T: bytemuck::NoUninit>(&self, data: &T) -> Uniform {
+    pub fn create_uniform<T: bytemuck::NoUninit>(&self, data: &T) -> Uniform<T> {
         let buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
             contents: bytemuck::cast_slice(std::slice::from_ref(data)),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
         let layout = std::alloc::Layout::new::<T>();
-        Uniform { buffer, layout, }
+        Uniform { raw: RawUniform { buffer: std::sync::Arc::new(buffer), layout, }, _type: std::marker::PhantomData }
     }
 
     pub fn create_storage_texture(&self, size: (u32, u32), format: StorageTextureFormat) -> Texture {
@@ -775,7 +1135,7 @@ impl Ctx {
         });
         let view = texture.create_view(&Default::default());
 
-        Texture { texture, view }
+        Texture { texture, view: std::sync::Arc::new(view) }
     }
 
     pub fn create_storage_texture_with_data<T: bytemuck::NoUninit>(
@@ -805,7 +1165,7 @@ impl Ctx {
         );
         let view = texture.create_view(&Default::default());
 
-        Texture { texture, view }
+        Texture { texture, view: std::sync::Arc::new(view) }
     }
 
     pub fn create_texture(&self, size: (u32, u32), format: wgpu::TextureFormat) -> Texture {
@@ -824,7 +1184,111 @@ impl Ctx {
         });
         let view = texture.create_view(&Default::default());
 
-        Texture { texture, view }
+        Texture { texture, view: std::sync::Arc::new(view) }
+    }
+
+    /// Loads a png, keeping the stored values. See `create_texture_from_png_ex`.
+    #[cfg(feature = "images")]
+    pub fn create_texture_from_png(&self, path: &std::path::Path, format: wgpu::TextureFormat) -> Result<Texture, CtxError> {
+        self.create_texture_from_png_ex(Either::A(path), format, false, false)
+    }
+
+    /// Same as `create_texture_from_png`, but decodes a png that is already in memory.
+    #[cfg(feature = "images")]
+    pub fn create_texture_from_png_bytes(&self, png: &[u8], format: wgpu::TextureFormat) -> Result<Texture, CtxError> {
+        self.create_texture_from_png_ex(Either::B(png), format, false, false)
+    }
+
+    /// Loads a png from a file or memory into a texture of `format`.
+    ///
+    /// R and Rg formats take the red and green channels, which are the grey level of grey pngs.
+    /// Srgb formats keep the png's colours encoded, so sampling decodes them.
+    /// With `linear`, Unorm and Float formats get the colours decoded to linear values instead, as colour images want.
+    /// Otherwise they get the stored values unchanged, so data survives exactly:
+    /// `u8` for Unorm and Uint formats, `0.0..=1.0` for Float formats.
+    /// 16 bit pngs keep their full precision in Float and Uint 32 bit formats, with Uint ones getting `u16` values,
+    /// and are reduced to 8 bits otherwise.
+    ///
+    /// Supports 8 bit Rgba, Bgra, Rg and R formats, plus Float and Uint 32 bit ones.
+    /// Images larger than `max_texture_dimension_2d` return an error.
+    /// The texture can also be bound as a storage texture if the format allows it.
+    /// With `mipmaps`, a full chain is generated with a box filter, in linear space for Srgb formats.
+    #[cfg(feature = "images")]
+    pub fn create_texture_from_png_ex(
+        &self,
+        path_or_png: Either<&std::path::Path, &[u8]>,
+        format: wgpu::TextureFormat,
+        mipmaps: bool,
+        linear: bool,
+    ) -> Result<Texture, CtxError> {
+        let mut png_format = PngFormat::new(format, linear)?;
+        let png = match path_or_png {
+            Either::A(path) => std::borrow::Cow::Owned(std::fs::read(path)?),
+            Either::B(png) => std::borrow::Cow::Borrowed(png),
+        };
+        let decode_error = |e: lodepng::Error| CtxError::Decode(e.to_string());
+
+        // Only the header is read, so oversized images are rejected before decoding.
+        let mut decoder = lodepng::Decoder::new();
+        let (width, height) = decoder.inspect(&png).map_err(decode_error)?;
+        let max_size = self.limits.max_texture_dimension_2d as usize;
+        if width > max_size || height > max_size {
+            return Err(CtxError::Decode(format!(
+                "a {}x{} image exceeds the texture size limit of {}", width, height, max_size
+            )));
+        }
+        let (width, height) = (width as u32, height as u32);
+
+        let texels: Vec<[u16; 4]> = if png_format.keeps_bit_depth(decoder.info_png().color.bitdepth()) {
+            png_format.max = u16::MAX;
+            match lodepng::decode_memory(&*png, lodepng::ColorType::RGBA, 16).map_err(decode_error)? {
+                // lodepng leaves 16 bit channels big endian, as pngs store them.
+                lodepng::Image::RGBA16(image) => image.buffer.iter().map(|p| [p.r, p.g, p.b, p.a].map(u16::from_be)).collect(),
+                _ => unreachable!("lodepng decodes to the requested color type"),
+            }
+        } else {
+            let image = lodepng::decode32(&*png).map_err(decode_error)?;
+            image.buffer.iter().map(|p| [p.r, p.g, p.b, p.a].map(u16::from)).collect()
+        };
+
+        let mip_level_count = if mipmaps { u32::BITS - width.max(height).leading_zeros() } else { 1 };
+        let data = if mip_level_count == 1 {
+            png_texels(&texels, &png_format)
+        } else {
+            let mut level: Vec<[f32; 4]> = texels.iter().map(|&texel| png_format.widen(texel)).collect();
+            let mut data = png_texels_f32(&level, &png_format);
+            let (mut w, mut h) = (width as usize, height as usize);
+            for _ in 1..mip_level_count {
+                (level, w, h) = downsample(&level, w, h, format.is_srgb());
+                data.extend(png_texels_f32(&level, &png_format));
+            }
+            data
+        };
+
+        let storage = format.guaranteed_format_features(self.features).allowed_usages
+            .contains(wgpu::TextureUsages::STORAGE_BINDING);
+
+        let texture = self.device.create_texture_with_data(
+            &self.queue,
+            &wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+                mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST
+                    | if storage { wgpu::TextureUsages::STORAGE_BINDING } else { wgpu::TextureUsages::empty() },
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &data,
+        );
+        let view = texture.create_view(&Default::default());
+
+        Ok(Texture { texture, view: std::sync::Arc::new(view) })
     }
 
     pub fn create_render_texture(&self, size: (u32, u32), format: wgpu::TextureFormat) -> RenderTexture {
@@ -962,7 +1426,7 @@ impl Ctx {
         }
     }
 
-    pub fn create_screen_copier<'a>(&self, src: &'a Texture, scaling_type: ScalingType) -> ScreenCopier {
+    pub fn create_screen_copier(&self, src: &Texture, scaling_type: ScalingType) -> ScreenCopier {
         let pipeline = self.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
             layout: Some(&self.copy_pipeline_layout),
@@ -1016,81 +1480,163 @@ impl Ctx {
         self.create_render_pipeline_ex(desc.into())
     }
 
+    pub fn try_create_render_pipeline<'a, 'b>(
+        &self, 
+        desc: RenderPipelineDescriptor<'a, 'b>
+    ) -> Result<RenderPipeline<'a>, CtxError> {
+        self.try_create_render_pipeline_ex(desc.into())
+    }
+
     pub fn create_render_pipeline_ex<'a, 'b>(
         &self, 
         desc: RenderPipelineDescriptorEx<'a, 'b>
     ) -> RenderPipeline<'a> {
-        let bind_group_layout_entries: &'static [wgpu::BindGroupLayoutEntry] = self.alloc.alloc_slice_fill_iter({
-            desc.inputs.iter()
-                .enumerate()
-                .map(|(i, input): (usize, &PipelineInput)| {
-                    wgpu::BindGroupLayoutEntry {
-                        binding: i as u32,
-                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                        count: None,
-                        ty: input.binding_type(),
-                    }
-                })
-        });
+        match self.try_create_render_pipeline_ex(desc) {
+            Ok(pipeline) => pipeline,
+            Err(e) => panic!("{}", e),
+        }
+    }
 
-        let bind_group_layout = self.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: bind_group_layout_entries,
-        });
+    pub fn try_create_render_pipeline_ex<'a, 'b>(
+        &self, 
+        desc: RenderPipelineDescriptorEx<'a, 'b>
+    ) -> Result<RenderPipeline<'a>, CtxError> {
+        Ok(self.try_create_render_pipeline_variants(desc, &[&[]])?.remove(0))
+    }
 
-        let bind_group_entries: &[wgpu::BindGroupEntry] = self.alloc.alloc_slice_fill_iter({
-            desc.inputs.iter()
-                .enumerate()
-                .map(|(i, input): (usize, &PipelineInput)| {
-                    wgpu::BindGroupEntry {
-                        binding: i as u32,
-                        resource: input.binding_resource(),
-                    }
+    pub fn create_render_pipeline_variants<'a>(
+        &self,
+        desc: RenderPipelineDescriptorEx<'a, '_>,
+        variants: &[&[(&str, f64)]],
+    ) -> Vec<RenderPipeline<'a>> {
+        match self.try_create_render_pipeline_variants(desc, variants) {
+            Ok(pipelines) => pipelines,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Creates a pipeline for each set of override constants, like `Ctx::create_compute_pipeline_variants`.
+    pub fn try_create_render_pipeline_variants<'a>(
+        &self,
+        desc: RenderPipelineDescriptorEx<'a, '_>,
+        variants: &[&[(&str, f64)]],
+    ) -> Result<Vec<RenderPipeline<'a>>, CtxError> {
+        if let Some((ref buffer, offset)) = desc.draw_indirect {
+            let indexed = matches!(desc.vertex_buffer, Either::A(vbo) if vbo.index_buffer.is_some());
+            check_indirect_buffer(buffer, offset, if indexed { 20 } else { 16 }, "Ctx::create_render_pipeline")?;
+        }
+        if !desc.inputs.is_empty() && !desc.bindings.is_empty() {
+            return Err(CtxError::Pipeline("Ctx::create_render_pipeline: inputs and bindings cannot both be used".to_string()));
+        }
+        self.check_group_count(desc.groups, "Ctx::create_render_pipeline")?;
+
+        let push_constant_size = self.push_constant_size(desc.push_constants, "Ctx::create_render_pipeline")?;
+        let shader = self.preprocess_shader(desc.shader, desc.shader_defines)?;
+        let (module, info) = self.validate_shader(&shader)?;
+        reflect_push_constants(&shader, &module, push_constant_size)?;
+        let variant_constants = self.variant_constants(&shader, &module, desc.constants, variants)?;
+
+        let visibility = wgpu::ShaderStages::VERTEX_FRAGMENT;
+        let group_entries = |swapped| if desc.bindings.is_empty() {
+            Ok(compute_entries(desc.inputs, &[], visibility, swapped))
+        } else {
+            let entry_points = [(desc.shader_vertex_entry, wgpu::ShaderStages::VERTEX), (desc.shader_fragment_entry, wgpu::ShaderStages::FRAGMENT)];
+            self.reflect_bindings(&shader, &module, &info, &desc.bindings, &entry_points, swapped)
+        };
+
+        let (bind_group_layout_entries, bind_group_entries) = group_entries(false)?;
+        let ping_pong_states = ping_pong_states(desc.inputs, &[], &desc.bindings);
+        let ping_pong_entries = if ping_pong_states.is_empty() { None } else { Some(group_entries(true)?) };
+        let (bind_groups, ping_pong, layout, pipelines) = self.error_scope(&shader, || {
+            let bind_groups = self.create_pipeline_bind_groups(&bind_group_layout_entries, bind_group_entries, desc.groups, visibility)?;
+            let ping_pong = ping_pong_entries.map(|(layout_entries, entries)| {
+                self.create_ping_pong_group(&bind_groups[0], ping_pong_states, &layout_entries, entries)
+            }).transpose()?;
+            let layout = self.create_pipeline_layout(&bind_groups, push_constant_size, visibility);
+            let pipelines: Vec<wgpu::RenderPipeline> = variant_constants.iter()
+                .map(|constants| {
+                    let wgpu_shader = self.create_shader_module(&shader, module.clone());
+                    self.create_wgpu_render_pipeline(&desc, &layout, &wgpu_shader, constants)
                 })
-        });
+                .collect();
+            Ok((bind_groups, ping_pong, std::sync::Arc::new(layout), pipelines))
+        })?;
+        let shader_modified = latest_modified(&shader.files);
+        let shader_defines = self.alloc_defines(desc.shader_defines);
+
+        let (vertex_buffer, primitives) = match desc.vertex_buffer {
+            Either::A(vbo) => (Some(vbo), vbo.primitives),
+            Either::B(primitives) => (None, primitives),
+        };
 
-        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: bind_group_entries,
-        });
+        Ok(pipelines.into_iter().zip(variant_constants).map(|(pipeline, constants)| RenderPipeline {
+            wgpu_pipeline: pipeline,
+            shader: desc.shader,
+            shader_defines,
+            shader_files: shader.files.clone(),
+            shader_modified,
+            constants,
+            shader_vertex_entry: desc.shader_vertex_entry,
+            shader_fragment_entry: desc.shader_fragment_entry,
+            layout: layout.clone(),
+            bind_groups: bind_groups.clone(),
+            ping_pong: ping_pong.clone(),
+            vertex_buffer,
+            instance_buffer: desc.instance_buffer,
+            primitives,
+            instance_range: desc.instance_range.clone(),
+            draw_range: desc.draw_range.clone(),
+            disable_depth_test: desc.disable_depth_test,
+            draw_indirect: desc.draw_indirect.clone(),
+            push_constants: vec![0; push_constant_size as usize],
+            output_format: desc.output_format,
+            blend_state: desc.blend_state,
+            cull_mode: desc.cull_mode,
+        }).collect())
+    }
 
-        let wgpu_shader = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(desc.shader.to_cow()),
-        });
+    /// Merges each variant over `constants`, checking them against the shader's overrides.
+    fn variant_constants(
+        &self,
+        shader: &PreprocessedShader,
+        module: &wgpu::naga::Module,
+        constants: &[(&str, f64)],
+        variants: &[&[(&str, f64)]],
+    ) -> Result<Vec<&'static [(&'static str, f64)]>, CtxError> {
+        variants.iter().map(|variant| {
+            let merged = merge_constants(constants, variant);
+            reflect_constants(shader, module, &merged)?;
+            Ok(self.alloc_constants(&merged))
+        }).collect()
+    }
 
-        let (vertex_buffer, primitives, vbuffers): (_, _, &'static _) = match (desc.vertex_buffer, desc.instance_buffer) {
-            (Either::A(vbo), None) => (
-                Some(vbo), 
-                vbo.primitives, 
-                self.alloc.alloc_slice_clone(&[vbo.vertex_layout.clone()])
-            ),
-            (Either::A(vbo), Some(ibo)) => (
-                Some(vbo), 
-                vbo.primitives, 
-                self.alloc.alloc_slice_clone(&[vbo.vertex_layout.clone(), ibo.vertex_layout.clone()])
-            ),
-            (Either::B(primitives), None) => (None, primitives, &[]),
-            (Either::B(primitives), Some(ibo))  => (
-                None, 
-                primitives, 
-                self.alloc.alloc_slice_clone(&[ibo.vertex_layout.clone()])
-            ),
+    fn create_wgpu_render_pipeline(
+        &self,
+        desc: &RenderPipelineDescriptorEx<'_, '_>,
+        layout: &wgpu::PipelineLayout,
+        wgpu_shader: &wgpu::ShaderModule,
+        constants: &[(&str, f64)],
+    ) -> wgpu::RenderPipeline {
+        // Only borrowed while the pipeline is created, so reloads don't grow `alloc`.
+        let (primitives, vbuffers) = match (&desc.vertex_buffer, desc.instance_buffer) {
+            (Either::A(vbo), None) => (vbo.primitives, vec![vbo.vertex_layout.clone()]),
+            (Either::A(vbo), Some(ibo)) => (vbo.primitives, vec![vbo.vertex_layout.clone(), ibo.vertex_layout.clone()]),
+            (Either::B(primitives), None) => (*primitives, Vec::new()),
+            (Either::B(primitives), Some(ibo))  => (*primitives, vec![ibo.vertex_layout.clone()]),
         };
 
-        let pipeline = self.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        let constants = constants_map(constants);
+        self.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
-            layout: Some(&self.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[&bind_group_layout],
-                push_constant_ranges: &[],
-            })),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
-                module: &wgpu_shader,
+                module: wgpu_shader,
                 entry_point: desc.shader_vertex_entry,
-                buffers: vbuffers,
-                compilation_options: Default::default(),
+                buffers: &vbuffers,
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
             },
             primitive: wgpu::PrimitiveState {
                 topology: primitives,
@@ -1118,133 +1664,628 @@ impl Ctx {
                 ..Default::default()
             },
             fragment: Some(wgpu::FragmentState {
-                module: &wgpu_shader,
+                module: wgpu_shader,
                 entry_point: desc.shader_fragment_entry,
                 targets: &[Some(wgpu::ColorTargetState {
                     format: desc.output_format,
                     blend: desc.blend_state,
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
-                compilation_options: Default::default(),
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
             }),
             multiview: None,
-        });
-
-        RenderPipeline {
-            wgpu_pipeline: pipeline,
-            shader: desc.shader,
-            bind_group: bind_group,
-            vertex_buffer,
-            instance_buffer: desc.instance_buffer,
-            instance_range: desc.instance_range,
-            draw_range: desc.draw_range,
-            disable_depth_test: desc.disable_depth_test,
-            output_format: desc.output_format,
-        }
+        })
     }
 
     pub fn create_compute_pipeline(
         &self, 
-        desc: ComputePipelineDescriptor<'_>
+        desc: ComputePipelineDescriptor
     ) -> ComputePipeline {
-        let input_count = desc.inputs.len();
-        let bind_group_layout_entries: &'static [wgpu::BindGroupLayoutEntry] = self.alloc.alloc_slice_fill_iter(
-            CustomChain::new(
-                desc.inputs.iter()
-                    .enumerate()
-                    .map(|(i, input): (usize, &PipelineInput)|
-                        wgpu::BindGroupLayoutEntry {
-                            binding: i as u32,
-                            visibility: wgpu::ShaderStages::COMPUTE,
-                            count: None,
-                            ty: input.binding_type(),
-                        }
-                    ),
-                desc.outputs.iter()
-                    .enumerate()
-                    .map(|(i, output): (usize, &ComputePipelineOutput)|
-                        wgpu::BindGroupLayoutEntry {
-                            binding: (i + input_count) as u32,
-                            visibility: wgpu::ShaderStages::COMPUTE,
-                            count: None,
-                            ty: output.binding_type(),
-                        }
-                    )
-            )
-        );
-
-        let bind_group_layout = self.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: bind_group_layout_entries,
-        });
+        match self.try_create_compute_pipeline(desc) {
+            Ok(pipeline) => pipeline,
+            Err(e) => panic!("{}", e),
+        }
+    }
 
-        let bind_group_entries: &[wgpu::BindGroupEntry] = self.alloc.alloc_slice_fill_iter(
-            CustomChain::new(
-                desc.inputs.iter()
-                    .enumerate()
-                    .map(|(i, input): (usize, &PipelineInput)| 
-                        wgpu::BindGroupEntry {
-                            binding: i as u32,
-                            resource: input.binding_resource(),
-                        }
-                    ),
-                desc.outputs.iter()
-                    .enumerate()
-                    .map(|(i, output): (usize, &ComputePipelineOutput)|
-                        wgpu::BindGroupEntry {
-                            binding: (i + input_count) as u32,
-                            resource: output.binding_resource(), 
-                        }
-                    )
-            )
+    pub fn try_create_compute_pipeline(
+        &self, 
+        desc: ComputePipelineDescriptor
+    ) -> Result<ComputePipeline, CtxError> {
+        Ok(self.try_create_compute_pipeline_variants(desc, &[&[]])?.remove(0))
+    }
+
+    pub fn create_compute_pipeline_variants(
+        &self,
+        desc: ComputePipelineDescriptor,
+        variants: &[&[(&str, f64)]],
+    ) -> Vec<ComputePipeline> {
+        match self.try_create_compute_pipeline_variants(desc, variants) {
+            Ok(pipelines) => pipelines,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Creates a pipeline for each set of override constants, which are applied on top of `desc.constants`.
+    /// The shader is preprocessed and validated once, and the pipelines share their bind groups and layout.
+    ///
+    /// Overrides can't size `@workgroup_size`, since naga can't evaluate them there yet,
+    /// so every variant has the same `workgroup_size`. Use `shader_defines` to vary it.
+    pub fn try_create_compute_pipeline_variants(
+        &self,
+        desc: ComputePipelineDescriptor,
+        variants: &[&[(&str, f64)]],
+    ) -> Result<Vec<ComputePipeline>, CtxError> {
+        if let DispatchCount::Indirect(ref buffer, offset) = desc.dispatch_count {
+            check_indirect_buffer(buffer, offset, 12, "Ctx::create_compute_pipeline")?;
+        }
+        if (!desc.inputs.is_empty() || !desc.outputs.is_empty()) && !desc.bindings.is_empty() {
+            return Err(CtxError::Pipeline("Ctx::create_compute_pipeline: inputs or outputs and bindings cannot both be used".to_string()));
+        }
+        self.check_group_count(desc.groups, "Ctx::create_compute_pipeline")?;
+
+        let push_constant_size = self.push_constant_size(desc.push_constants, "Ctx::create_compute_pipeline")?;
+        let shader = self.preprocess_shader(desc.shader, desc.shader_defines)?;
+        let (module, info) = self.validate_shader(&shader)?;
+        let base_workgroup = reflect_push_constants(&shader, &module, push_constant_size)?;
+
+        let variant_constants = self.variant_constants(&shader, &module, desc.constants, variants)?;
+        let workgroup_size = reflect_workgroup_size(&module, desc.shader_entry);
+        if let Some(dispatch_count) = desc.dispatch_count.workgroups(workgroup_size) {
+            self.check_dispatch_count(dispatch_count, base_workgroup, "Ctx::create_compute_pipeline")?;
+        }
+        self.limit_elements(&desc.dispatch_count, workgroup_size, base_workgroup);
+
+        let visibility = wgpu::ShaderStages::COMPUTE;
+        let group_entries = |swapped| if desc.bindings.is_empty() {
+            Ok(compute_entries(desc.inputs, desc.outputs, visibility, swapped))
+        } else {
+            self.reflect_bindings(&shader, &module, &info, &desc.bindings, &[(desc.shader_entry, visibility)], swapped)
+        };
+
+        let (bind_group_layout_entries, bind_group_entries) = group_entries(false)?;
+        let ping_pong_states = ping_pong_states(desc.inputs, desc.outputs, &desc.bindings);
+        let ping_pong_entries = if ping_pong_states.is_empty() { None } else { Some(group_entries(true)?) };
+        let (bind_groups, ping_pong, layout, pipelines) = self.error_scope(&shader, || {
+            let bind_groups = self.create_pipeline_bind_groups(&bind_group_layout_entries, bind_group_entries, desc.groups, visibility)?;
+            let ping_pong = ping_pong_entries.map(|(layout_entries, entries)| {
+                self.create_ping_pong_group(&bind_groups[0], ping_pong_states, &layout_entries, entries)
+            }).transpose()?;
+            let layout = self.create_pipeline_layout(&bind_groups, push_constant_size, visibility);
+            // A module per variant, since the GL backend caches programs by module and entry point, ignoring constants.
+            let pipelines: Vec<wgpu::ComputePipeline> = variant_constants.iter()
+                .map(|constants| {
+                    let wgpu_shader = self.create_shader_module(&shader, module.clone());
+                    self.create_wgpu_compute_pipeline(&layout, &wgpu_shader, desc.shader_entry, constants)
+                })
+                .collect();
+            Ok((bind_groups, ping_pong, std::sync::Arc::new(layout), pipelines))
+        })?;
+        let shader_modified = latest_modified(&shader.files);
+        let shader_defines = self.alloc_defines(desc.shader_defines);
+
+        Ok(pipelines.into_iter().zip(variant_constants).map(|(pipeline, constants)| ComputePipeline {
+            wgpu_pipeline: pipeline,
+            shader: desc.shader,
+            shader_defines,
+            shader_files: shader.files.clone(),
+            shader_modified,
+            layout: layout.clone(),
+            shader_entry: desc.shader_entry,
+            constants,
+            workgroup_size,
+            base_workgroup,
+            bind_groups: bind_groups.clone(),
+            ping_pong: ping_pong.clone(),
+            dispatch_count: desc.dispatch_count.clone(),
+            push_constants: vec![0; push_constant_size as usize],
+        }).collect())
+    }
+
+    /// Creates a group that can be shared between pipelines with `PipelineGroup::Shared`.
+    /// The inputs are visible to compute, vertex and fragment shaders.
+    pub fn create_bind_group(&self, inputs: &[PipelineInput]) -> BindGroup {
+        assert!(
+            inputs.iter().all(|input| input.ping_pong_state().is_none()),
+            "Ctx::create_bind_group: ping-pong resources can only be used in @group(0)"
         );
+        let (layout_entries, entries) = compute_entries(inputs, &[], wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE, false);
+        self.create_bind_group_from_entries(&layout_entries, entries)
+    }
 
-        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+    fn create_bind_group_from_entries(&self, layout_entries: &[wgpu::BindGroupLayoutEntry], entries: Vec<GroupEntry>) -> BindGroup {
+        let layout = self.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
-            layout: &bind_group_layout,
-            entries: bind_group_entries,
+            entries: layout_entries,
         });
 
-        let wgpu_shader = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(desc.shader.to_cow()),
-        });
+        BindGroup {
+            version: std::sync::Arc::new(GroupVersion::new(&self.device, &layout, &entries)),
+            layout: std::sync::Arc::new(layout),
+            layout_entries: layout_entries.to_vec(),
+            entries,
+        }
+    }
+
+    /// Creates a group with the same layout as `group`, or returns why `layout_entries` don't match it.
+    fn recreate_bind_group(
+        &self,
+        group: &BindGroup,
+        layout_entries: &[wgpu::BindGroupLayoutEntry],
+        entries: Vec<GroupEntry>,
+    ) -> Result<BindGroup, String> {
+        if layout_entries.len() != group.layout_entries.len() {
+            return Err(format!("expected {} resources but {} were given", group.layout_entries.len(), layout_entries.len()));
+        }
+        for (old, new) in group.layout_entries.iter().zip(layout_entries) {
+            if old.binding != new.binding || old.ty != new.ty {
+                return Err(format!("binding {} expects {:?} but {:?} was given", old.binding, old.ty, new.ty));
+            }
+        }
+
+        Ok(BindGroup {
+            version: std::sync::Arc::new(GroupVersion::new(&self.device, &group.layout, &entries)),
+            layout: group.layout.clone(),
+            layout_entries: group.layout_entries.clone(),
+            entries,
+        })
+    }
+
+    /// Creates the orientation of `group` used while its ping-pong resources are swapped.
+    fn create_ping_pong_group(
+        &self,
+        group: &BindGroup,
+        states: Vec<std::sync::Arc<std::sync::atomic::AtomicBool>>,
+        layout_entries: &[wgpu::BindGroupLayoutEntry],
+        entries: Vec<GroupEntry>,
+    ) -> Result<PingPongGroup, CtxError> {
+        let bind_group = self.recreate_bind_group(group, layout_entries, entries)
+            .map_err(|e| CtxError::Pipeline(format!("PingPong: both resources must have the same binding type, but {}", e)))?;
+        Ok(PingPongGroup { bind_group, states })
+    }
+
+    /// Creates a replacement for `group` and, if there are ping-pong resources, its swapped orientation.
+    fn rebind_group(
+        &self,
+        group: &BindGroup,
+        (layout_entries, entries): (Vec<wgpu::BindGroupLayoutEntry>, Vec<GroupEntry>),
+        states: Vec<std::sync::Arc<std::sync::atomic::AtomicBool>>,
+        swapped_entries: impl FnOnce() -> Result<(Vec<wgpu::BindGroupLayoutEntry>, Vec<GroupEntry>), ShaderError>,
+    ) -> Result<(BindGroup, Option<PingPongGroup>), String> {
+        let group = self.recreate_bind_group(group, &layout_entries, entries)?;
+        let ping_pong = if states.is_empty() { None } else {
+            let (layout_entries, entries) = swapped_entries().map_err(|e| e.to_string())?;
+            let bind_group = self.recreate_bind_group(&group, &layout_entries, entries)
+                .map_err(|e| format!("both sides of a ping-pong resource must have the same binding type, but {}", e))?;
+            Some(PingPongGroup { bind_group, states })
+        };
+        Ok((group, ping_pong))
+    }
+
+    /// Returns group 0 followed by `groups`.
+    fn create_pipeline_bind_groups(
+        &self,
+        layout_entries: &[wgpu::BindGroupLayoutEntry],
+        entries: Vec<GroupEntry>,
+        groups: &[PipelineGroup],
+        visibility: wgpu::ShaderStages,
+    ) -> Result<Vec<BindGroup>, CtxError> {
+        std::iter::once(Ok(self.create_bind_group_from_entries(layout_entries, entries)))
+            .chain(groups.iter().map(|group| match *group {
+                PipelineGroup::Inputs(inputs) => {
+                    if inputs.iter().any(|input| input.ping_pong_state().is_some()) {
+                        return Err(CtxError::Pipeline("PipelineGroup::Inputs: ping-pong resources can only be used in @group(0)".to_string()));
+                    }
+                    let (layout_entries, entries) = compute_entries(inputs, &[], visibility, false);
+                    Ok(self.create_bind_group_from_entries(&layout_entries, entries))
+                }
+                PipelineGroup::Shared(group) => Ok(group.clone()),
+            }))
+            .collect()
+    }
 
-        let layout = self.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    fn create_pipeline_layout(
+        &self,
+        bind_groups: &[BindGroup],
+        push_constant_size: u32,
+        stages: wgpu::ShaderStages,
+    ) -> wgpu::PipelineLayout {
+        let bind_group_layouts: Vec<&wgpu::BindGroupLayout> = bind_groups.iter().map(|group| &*group.layout).collect();
+        let push_constant_range = wgpu::PushConstantRange { stages, range: 0..push_constant_size };
+        self.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
-        });
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: if push_constant_size > 0 { std::slice::from_ref(&push_constant_range) } else { &[] },
+        })
+    }
 
-        let pipeline = self.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+    /// Checks that push constants of the given layout can be used, returning their size.
+    fn push_constant_size(&self, layout: Option<std::alloc::Layout>, caller: &str) -> Result<u32, CtxError> {
+        let Some(layout) = layout else { return Ok(0) };
+        if !self.features.contains(wgpu::Features::PUSH_CONSTANTS) {
+            return Err(CtxError::MissingFeatures(wgpu::Features::PUSH_CONSTANTS));
+        }
+        if layout.size() == 0 || layout.size() % 4 != 0 {
+            return Err(CtxError::Pipeline(format!(
+                "{}: the push constant size must be a non-zero multiple of 4, not {}", caller, layout.size()
+            )));
+        }
+        if layout.size() > self.limits.max_push_constant_size as usize {
+            return Err(CtxError::Pipeline(format!(
+                "{}: the push constants are {} bytes but at most {} are supported", caller, layout.size(), self.limits.max_push_constant_size
+            )));
+        }
+        Ok(layout.size() as u32)
+    }
+
+    /// Checks that `@group(0)` and `groups` fit within the device's bind group limit.
+    fn check_group_count(&self, groups: &[PipelineGroup], caller: &str) -> Result<(), CtxError> {
+        if groups.len() >= self.limits.max_bind_groups as usize {
+            return Err(CtxError::Pipeline(format!("{}: at most {} bind groups are supported", caller, self.limits.max_bind_groups)));
+        }
+        Ok(())
+    }
+
+    fn create_wgpu_compute_pipeline(
+        &self,
+        layout: &wgpu::PipelineLayout,
+        wgpu_shader: &wgpu::ShaderModule,
+        entry_point: &str,
+        constants: &[(&str, f64)],
+    ) -> wgpu::ComputePipeline {
+        self.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
             label: None,
-            layout: Some(&layout),
-            module: &wgpu_shader,
-            entry_point: desc.shader_entry,
-            compilation_options: Default::default(),
-        });
+            layout: Some(layout),
+            module: wgpu_shader,
+            entry_point,
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &constants_map(constants),
+                ..Default::default()
+            },
+        })
+    }
 
-        ComputePipeline {
-            wgpu_pipeline: pipeline,
-            shader: desc.shader,
-            layout,
-            shader_entry: desc.shader_entry,
-            bind_group,
-            bind_group_layout,
-            dispatch_count: desc.dispatch_count,
+    /// Matches `bindings` to the shader's `@group(0)` variables by name,
+    /// checking each resource against the declared type.
+    fn reflect_bindings<'a>(
+        &self,
+        shader: &PreprocessedShader,
+        module: &wgpu::naga::Module,
+        info: &wgpu::naga::valid::ModuleInfo,
+        bindings: &Bindings<'a>,
+        entry_points: &[(&str, wgpu::ShaderStages)],
+        swapped: bool,
+    ) -> Result<(Vec<wgpu::BindGroupLayoutEntry>, Vec<GroupEntry>), ShaderError> {
+        let mut layout_entries = Vec::new();
+        let mut entries = Vec::new();
+        let mut bound = vec![false; bindings.entries.len()];
+        // The layout entry of each global that was reflected.
+        let mut reflected = Vec::new();
+
+        let functions: Vec<(wgpu::ShaderStages, &wgpu::naga::valid::FunctionInfo)> = entry_points.iter()
+            .filter_map(|&(name, stage)| {
+                let i = module.entry_points.iter().position(|entry| entry.name == name)?;
+                Some((stage, info.get_entry_point(i)))
+            })
+            .collect();
+
+        for (handle, var) in module.global_variables.iter() {
+            let Some(ref binding) = var.binding else { continue };
+            let name = var.name.as_deref().unwrap_or("");
+            let error = |message: String| shader.error(
+                format!("binding `{}`: {}", name, message),
+                Some(module.global_variables.get_span(handle).location(&shader.source)),
+            );
+
+            // Groups after 0 come from `groups`.
+            if binding.group != 0 { continue }
+
+            // Declarations the entry points never reach are left out of the layout.
+            let visibility = functions.iter()
+                .filter(|(_, function)| !function[handle].is_empty())
+                .fold(wgpu::ShaderStages::NONE, |stages, &(stage, _)| stages | stage);
+            let position = bindings.entries.iter().position(|&(n, _)| n == name);
+            let Some(i) = position else {
+                if visibility.is_empty() { continue }
+                return Err(error("no resource was bound to this name".to_string()));
+            };
+            bound[i] = true;
+            if visibility.is_empty() { continue }
+
+            let resource = bindings.entries[i].1.orient(swapped);
+            let ty = self.reflect_binding_type(module, var, resource, visibility).map_err(error)?;
+            reflected.push((handle, layout_entries.len()));
+            layout_entries.push(wgpu::BindGroupLayoutEntry { binding: binding.binding, visibility, count: None, ty });
+            entries.push(GroupEntry { binding: binding.binding, resource: resource.group_resource() });
+        }
+
+        // Samplers used with textures that can't be filtered must not filter.
+        let entry_of = |handle| reflected.iter().find(|&&(h, _)| h == handle).map(|&(_, i)| i);
+        for key in functions.iter().flat_map(|(_, function)| function.sampling_set.iter()) {
+            let (Some(image), Some(sampler)) = (entry_of(key.image), entry_of(key.sampler)) else { continue };
+            let unfilterable = matches!(
+                layout_entries[image].ty,
+                wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Float { filterable: false }, .. }
+            );
+            if unfilterable && layout_entries[sampler].ty == wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering) {
+                layout_entries[sampler].ty = wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering);
+            }
+        }
+
+        if let Some(i) = bound.iter().position(|&b| !b) {
+            let message = format!("binding `{}` is not declared in the shader", bindings.entries[i].0);
+            return Err(shader.error(message, None));
+        }
+
+        Ok((layout_entries, entries))
+    }
+
+    /// Returns the binding type for `resource` bound to `var`, or why they are incompatible.
+    fn reflect_binding_type(
+        &self,
+        module: &wgpu::naga::Module,
+        var: &wgpu::naga::GlobalVariable,
+        resource: PipelineResource,
+        visibility: wgpu::ShaderStages,
+    ) -> Result<wgpu::BindingType, String> {
+        use wgpu::naga::{AddressSpace, ArraySize, ImageClass, ImageDimension, ScalarKind, StorageAccess, TypeInner};
+
+        let inner = &module.types[var.ty].inner;
+        let declared = match (var.space, inner) {
+            (AddressSpace::Uniform, _) => "uniform buffer",
+            (AddressSpace::Storage { .. }, _) => "storage buffer",
+            (_, TypeInner::Image { class: ImageClass::Storage { .. }, .. }) => "storage texture",
+            (_, TypeInner::Image { .. }) => "texture",
+            (_, TypeInner::Sampler { .. }) => "sampler",
+            _ => "unsupported type",
+        };
+        let mismatch = || format!("the shader declares a {} but a {} was bound", declared, resource.kind());
+
+        match (var.space, inner, resource) {
+            (AddressSpace::Uniform, _, PipelineResource::Uniform(uniform)) => {
+                let size = inner.size(module.to_ctx()) as usize;
+                let bound_size = uniform.layout.pad_to_align().size();
+                if size != bound_size {
+                    return Err(format!("the shader type is {} bytes but the uniform is {} bytes", size, bound_size));
+                }
+
+                Ok(wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                })
+            }
+            (AddressSpace::Storage { access }, _, PipelineResource::StorageBuffer(ssbo)) => {
+                let writable = access.contains(StorageAccess::STORE);
+                if writable && visibility.contains(wgpu::ShaderStages::VERTEX) {
+                    return Err("read_write storage cannot be used in vertex shaders".to_string());
+                }
+
+                let element_size = ssbo.layout.pad_to_align().size() as u32;
+                match *inner {
+                    TypeInner::Array { size: ArraySize::Dynamic, stride, .. } => if stride != element_size {
+                        return Err(format!("the shader array stride is {} bytes but the buffer elements are {} bytes", stride, element_size));
+                    },
+                    _ => {
+                        let size = inner.size(module.to_ctx()) as u64;
+                        if size > ssbo.buffer.size() {
+                            return Err(format!("the shader type is {} bytes but the buffer is {} bytes", size, ssbo.buffer.size()));
+                        }
+                    }
+                }
+
+                Ok(wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: !writable },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                })
+            }
+            (AddressSpace::Handle, &TypeInner::Image { dim, arrayed, class }, PipelineResource::Texture(texture)) => {
+                if dim != ImageDimension::D2 || arrayed {
+                    return Err("only 2d, non arrayed textures are supported".to_string());
+                }
+
+                let format = texture.texture.format();
+                let usage = texture.texture.usage();
+                match class {
+                    ImageClass::Sampled { multi: true, .. } | ImageClass::Depth { multi: true } => {
+                        Err("multisampled textures are not supported".to_string())
+                    }
+                    ImageClass::Sampled { kind, .. } => {
+                        if !usage.contains(wgpu::TextureUsages::TEXTURE_BINDING) {
+                            return Err("the texture was not created with TEXTURE_BINDING usage".to_string());
+                        }
+
+                        let sample_type = match (kind, format.sample_type(None, Some(self.features))) {
+                            (ScalarKind::Float, Some(ty @ wgpu::TextureSampleType::Float { .. })) => ty,
+                            (ScalarKind::Float, Some(wgpu::TextureSampleType::Depth)) => wgpu::TextureSampleType::Float { filterable: false },
+                            (ScalarKind::Sint, Some(ty @ wgpu::TextureSampleType::Sint)) => ty,
+                            (ScalarKind::Uint, Some(ty @ wgpu::TextureSampleType::Uint)) => ty,
+                            _ => return Err(format!("a texture with {:?} components cannot be sampled from a {:?} texture", kind, format)),
+                        };
+
+                        Ok(wgpu::BindingType::Texture {
+                            sample_type,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        })
+                    }
+                    ImageClass::Depth { .. } => {
+                        if format.sample_type(None, Some(self.features)) != Some(wgpu::TextureSampleType::Depth) {
+                            return Err(format!("the shader declares a depth texture but the texture is {:?}", format));
+                        }
+
+                        Ok(wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        })
+                    }
+                    ImageClass::Storage { format: declared_format, access } => {
+                        if !usage.contains(wgpu::TextureUsages::STORAGE_BINDING) {
+                            return Err("the texture was not created with STORAGE_BINDING usage".to_string());
+                        }
+
+                        let declared_format = storage_texture_format(declared_format);
+                        if declared_format != format {
+                            return Err(format!("the shader format is {:?} but the texture is {:?}", declared_format, format));
+                        }
+
+                        let access = match (access.contains(StorageAccess::LOAD), access.contains(StorageAccess::STORE)) {
+                            (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+                            (false, true) => wgpu::StorageTextureAccess::WriteOnly,
+                            _ => wgpu::StorageTextureAccess::ReadOnly,
+                        };
+                        let format_flags = format.guaranteed_format_features(self.features).flags;
+                        if access == wgpu::StorageTextureAccess::ReadWrite
+                            && !self.features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+                            && !format_flags.contains(wgpu::TextureFormatFeatureFlags::STORAGE_READ_WRITE)
+                        {
+                            return Err(format!("{:?} does not support read_write storage access", format));
+                        }
+
+                        Ok(wgpu::BindingType::StorageTexture {
+                            access,
+                            format,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        })
+                    }
+                }
+            }
+            (AddressSpace::Handle, &TypeInner::Sampler { comparison }, PipelineResource::Sampler(_)) => {
+                Ok(wgpu::BindingType::Sampler(if comparison {
+                    wgpu::SamplerBindingType::Comparison
+                } else {
+                    wgpu::SamplerBindingType::Filtering
+                }))
+            }
+            _ => Err(mismatch()),
+        }
+    }
+
+    /// Parses and validates a shader with naga.
+    fn validate_shader(&self, shader: &PreprocessedShader) -> Result<(wgpu::naga::Module, wgpu::naga::valid::ModuleInfo), ShaderError> {
+        use wgpu::naga;
+
+        let module = naga::front::wgsl::parse_str(&shader.source).map_err(|e| {
+            match override_workgroup_size(&shader.source) {
+                Some((range, name)) => {
+                    let message = format!("`@workgroup_size` cannot use the override `{}` because naga can't evaluate it there, use a #define instead", name);
+                    shader.error(message, Some(naga::Span::new(range.start as u32, range.end as u32).location(&shader.source)))
+                }
+                None => shader.error(e.message().to_string(), e.location(&shader.source)),
+            }
+        })?;
+
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+            .validate(&module)
+            .map_err(|e| {
+                let mut message = e.as_inner().to_string();
+                let mut source = std::error::Error::source(e.as_inner());
+                while let Some(error) = source {
+                    message = format!("{}: {}", message, error);
+                    source = error.source();
+                }
+                shader.error(message, e.location(&shader.source))
+            })?;
+
+        Ok((module, info))
+    }
+
+    /// Creates a pipeline from a validated shader with `f`.
+    /// Errors raised by wgpu while creating the pipeline are caught and returned.
+    fn compile_shader<T>(
+        &self,
+        shader: &PreprocessedShader,
+        module: wgpu::naga::Module,
+        f: impl FnOnce(&wgpu::ShaderModule) -> T,
+    ) -> Result<T, CtxError> {
+        self.error_scope(shader, || Ok(f(&self.create_shader_module(shader, module))))
+    }
+
+    /// Runs `f`, returning the first error wgpu raises while it creates resources for `shader`.
+    fn error_scope<T>(&self, shader: &PreprocessedShader, f: impl FnOnce() -> Result<T, CtxError>) -> Result<T, CtxError> {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let result = f();
+        let error = pollster::block_on(self.device.pop_error_scope());
+        let t = result?;
+        match error {
+            Some(e) => Err(shader.error(e.to_string(), None).into()),
+            None => Ok(t),
         }
     }
 
+    fn create_shader_module(&self, shader: &PreprocessedShader, module: wgpu::naga::Module) -> wgpu::ShaderModule {
+        self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&shader.file_names[0]),
+            source: wgpu::ShaderSource::Naga(std::borrow::Cow::Owned(module)),
+        })
+    }
+
+    /// Expands `#include`s, `#define`s and conditionals in a shader.
+    ///
+    /// `#include "file.wgsl"` is looked up in `shader_includes`, then relative to the including file.
+    /// Inside a virtual include, `shader_includes` is searched next to its name first, e.g. `lib/file.wgsl`
+    /// from `lib/noise.wgsl`, and files are looked up relative to the real file that included it.
+    /// Each file is included at most once.
+    /// `defines` are applied before the shader's own `#define NAME value` lines.
+    /// Defined names are replaced wherever they appear as whole identifiers,
+    /// and `#ifdef`, `#ifndef`, `#else` and `#endif` select lines based on which names are defined.
+    pub fn preprocess_shader(&self, shader: ShaderSource, defines: &[(&str, &str)]) -> Result<PreprocessedShader, CtxError> {
+        preprocess(shader, defines, &self.shader_includes.borrow())
+    }
+
+    fn alloc_constants(&self, constants: &[(&str, f64)]) -> &'static [(&'static str, f64)] {
+        self.alloc.alloc_slice_fill_iter(constants.iter().map(|&(name, value)| -> (&'static str, f64) {
+            (self.alloc.alloc_str(name), value)
+        }))
+    }
+
+    fn alloc_defines(&self, defines: &[(&str, &str)]) -> &'static [(&'static str, &'static str)] {
+        self.alloc.alloc_slice_fill_iter(defines.iter().map(|&(name, value)| -> (&'static str, &'static str) {
+            (self.alloc.alloc_str(name), self.alloc.alloc_str(value))
+        }))
+    }
+
+    /// Returns the new shader if any file it was built from has changed since `last_modified`.
+    fn reload_shader(
+        &self,
+        shader: ShaderSource,
+        defines: &[(&str, &str)],
+        files: &mut Vec<std::path::PathBuf>,
+        last_modified: &mut Option<std::time::SystemTime>,
+    ) -> Result<Option<PreprocessedShader>, CtxError> {
+        let modified = latest_modified(files);
+        if modified.is_none() || modified == *last_modified { return Ok(None) }
+
+        // Updated before compiling so that a broken shader is only reported once.
+        *last_modified = modified;
+        let shader = self.preprocess_shader(shader, defines)?;
+        files.clone_from(&shader.files);
+        Ok(Some(shader))
+    }
+
     pub fn run_render_pipeline<'a>(&self, pass: &mut wgpu::RenderPass<'a>, pipeline: &'a RenderPipeline) {
         pass.set_pipeline(&pipeline.wgpu_pipeline);
-        pass.set_bind_group(0, &pipeline.bind_group, &[]);
+        for (i, group) in pipeline.bind_groups.iter().enumerate() {
+            let group = match pipeline.ping_pong {
+                Some(ref ping_pong) if i == 0 && ping_pong.is_swapped() => &ping_pong.bind_group,
+                _ => group,
+            };
+            pass.set_bind_group(i as u32, group.bind_group(self), &[]);
+        }
+        if !pipeline.push_constants.is_empty() {
+            pass.set_push_constants(wgpu::ShaderStages::VERTEX_FRAGMENT, 0, &pipeline.push_constants);
+        }
         let draw_range = pipeline.draw_range.clone();
         let instance_range = pipeline.instance_range.clone();
 
-        match (pipeline.vertex_buffer, pipeline.instance_buffer) {
-            (None, None) => pass.draw(draw_range, instance_range),
+        let indexed = match (pipeline.vertex_buffer, pipeline.instance_buffer) {
+            (None, None) => false,
             (Some(v), i) => {
-                pass.set_vertex_buffer(0, v.vertex_buffer.slice(..));
+                pass.set_vertex_buffer(0, v.vertex_buffer.latest().slice(..));
 
 
                 if let Some(i) = i {
@@ -1253,32 +2294,105 @@ impl Ctx {
 
                 if let Some(ref ib) = v.index_buffer {
                     pass.set_index_buffer(ib.buffer.slice(..), ib.format);
-                    pass.draw_indexed(draw_range, 0, instance_range);
+                    true
                 } else {
-                    pass.draw(draw_range, instance_range);
+                    false
                 }
             },
             (None, Some(i)) => {
                 pass.set_vertex_buffer(0, i.buffer.slice(..));
-                pass.draw(draw_range, instance_range);
+                false
             }
+        };
+
+        match (&pipeline.draw_indirect, indexed) {
+            (None, false) => pass.draw(draw_range, instance_range),
+            (None, true) => pass.draw_indexed(draw_range, 0, instance_range),
+            (Some((buffer, offset)), false) => pass.draw_indirect(buffer.latest(), *offset),
+            (Some((buffer, offset)), true) => pass.draw_indexed_indirect(buffer.latest(), *offset),
         }
     }
 
     pub fn run_compute_pipeline<'a>(&self, pass: &mut wgpu::ComputePass<'a>, pipeline: &'a ComputePipeline) {
-        //let t = std::time::Instant::now();
-        //if let ShaderSource::File(path) = pipeline.shader {
-        //    if let Ok(metadata) = std::fs::metadata(path) {
-        //        if let Ok(modified) = metadata.modified() {
-        //            println!("modified: {:?}", modified);
-        //        }
-        //    }
-        //}
-        //println!("{}", t.elapsed().as_nanos());
+        self.set_compute_pipeline(pass, pipeline);
+        match pipeline.dispatch_count {
+            // Checked against the limit when the pipeline was created or reloaded,
+            // and for `Elements` by `RawStorageBuffer::resize` since.
+            DispatchCount::Direct(_) | DispatchCount::Invocations(_) | DispatchCount::Elements(_) => {
+                // In case the count was assigned after the pipeline was created.
+                self.limit_elements(&pipeline.dispatch_count, pipeline.workgroup_size, pipeline.base_workgroup);
+                let dispatch_count = pipeline.dispatch_count.workgroups(pipeline.workgroup_size).unwrap();
+                self.dispatch_split(pass, pipeline, dispatch_count)
+            }
+            DispatchCount::Indirect(ref buffer, offset) => {
+                if let Some(base_offset) = pipeline.base_workgroup {
+                    pass.set_push_constants(base_offset, bytemuck::cast_slice(&[0u32; 3]));
+                }
+                pass.dispatch_workgroups_indirect(buffer.latest(), offset);
+            }
+        }
+    }
+
+    /// Runs the pipeline with `dispatch_count` instead of its own.
+    ///
+    /// Dispatches larger than `max_compute_workgroups_per_dimension` are split into several,
+    /// which requires the shader's push constants to have a `base_workgroup: vec3<u32>` member.
+    /// It is set to the first workgroup of each dispatch (or zero if not split),
+    /// so the shader should add `base_workgroup * workgroup_size` to `global_invocation_id`.
+    /// Push constants are native only, so elsewhere a dispatch over the limit returns an error and nothing is run.
+    pub fn run_compute_pipeline_with<'a>(
+        &self,
+        pass: &mut wgpu::ComputePass<'a>,
+        pipeline: &'a ComputePipeline,
+        dispatch_count: [u32; 3],
+    ) -> Result<(), CtxError> {
+        self.check_dispatch_count(dispatch_count, pipeline.base_workgroup, "Ctx::run_compute_pipeline_with")?;
+        self.set_compute_pipeline(pass, pipeline);
+        self.dispatch_split(pass, pipeline, dispatch_count);
+        Ok(())
+    }
+
+    /// Stops `RawStorageBuffer::resize` from growing an `Elements` buffer past what can be dispatched unsplit.
+    fn limit_elements(&self, dispatch_count: &DispatchCount, workgroup_size: [u32; 3], base_workgroup: Option<u32>) {
+        if let (DispatchCount::Elements(buffer), None) = (dispatch_count, base_workgroup) {
+            let max_len = self.limits.max_compute_workgroups_per_dimension.saturating_mul(workgroup_size[0]);
+            buffer.latest().max_len.fetch_min(max_len, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    fn check_dispatch_count(&self, dispatch_count: [u32; 3], base_workgroup: Option<u32>, caller: &str) -> Result<(), CtxError> {
+        let limit = self.limits.max_compute_workgroups_per_dimension;
+        if base_workgroup.is_none() && dispatch_count.iter().any(|&n| n > limit) {
+            return Err(CtxError::Pipeline(format!(
+                "{}: {:?} workgroups exceeds the limit of {} per dimension. Splitting the dispatch needs \
+                a `base_workgroup: vec3<u32>` member in the shader's push constants, which need Features::PUSH_CONSTANTS",
+                caller, dispatch_count, limit
+            )));
+        }
+        Ok(())
+    }
+
+    fn set_compute_pipeline<'a>(&self, pass: &mut wgpu::ComputePass<'a>, pipeline: &'a ComputePipeline) {
         pass.set_pipeline(&pipeline.wgpu_pipeline);
-        pass.set_bind_group(0, &pipeline.bind_group, &[]);
-        let [x, y, z] = pipeline.dispatch_count;
-        pass.dispatch_workgroups(x, y, z);
+        for (i, group) in pipeline.bind_groups.iter().enumerate() {
+            let group = match pipeline.ping_pong {
+                Some(ref ping_pong) if i == 0 && ping_pong.is_swapped() => &ping_pong.bind_group,
+                _ => group,
+            };
+            pass.set_bind_group(i as u32, group.bind_group(self), &[]);
+        }
+        if !pipeline.push_constants.is_empty() {
+            pass.set_push_constants(0, &pipeline.push_constants);
+        }
+    }
+
+    fn dispatch_split(&self, pass: &mut wgpu::ComputePass, pipeline: &ComputePipeline, dispatch_count: [u32; 3]) {
+        for (base, [x, y, z]) in split_dispatch(dispatch_count, self.limits.max_compute_workgroups_per_dimension) {
+            if let Some(offset) = pipeline.base_workgroup {
+                pass.set_push_constants(offset, bytemuck::cast_slice(&base));
+            }
+            pass.dispatch_workgroups(x, y, z);
+        }
     }
 
     pub fn run_render_pass(
@@ -1295,7 +2409,7 @@ impl Ctx {
             )
         }
 
-        if passes.len() > 0 {
+        if !passes.is_empty() {
             let disable_depth_test = passes[0].disable_depth_test;
             if passes[1..].iter().any(|pass| pass.disable_depth_test != disable_depth_test) {
                 panic!("run_render_pass: RenderPipeline disable_depth_test must not be varied across pipelines in a single pass");
@@ -1344,19 +2458,136 @@ impl Ctx {
         }
     }
 
+    /// Like `run_compute_pass`, with a dispatch count for each pipeline. See `run_compute_pipeline_with`.
+    /// The counts are all checked before the pass begins.
+    pub fn run_compute_pass_with(
+        &self, 
+        encoder: &mut wgpu::CommandEncoder,
+        passes: &[(&ComputePipeline, [u32; 3])]
+    ) -> Result<(), CtxError> {
+        for &(pipeline, dispatch_count) in passes {
+            self.check_dispatch_count(dispatch_count, pipeline.base_workgroup, "Ctx::run_compute_pass_with")?;
+        }
+        let mut pass = encoder.begin_compute_pass(&Default::default());
+        for &(pipeline, dispatch_count) in passes {
+            self.set_compute_pipeline(&mut pass, pipeline);
+            self.dispatch_split(&mut pass, pipeline, dispatch_count);
+        }
+        Ok(())
+    }
+
+    /// Zeroes every mip level and layer of `texture`.
+    ///
+    /// Without `CLEAR_TEXTURE`, color textures are overwritten from a zeroed buffer and need `COPY_DST`,
+    /// and depth and stencil textures are cleared as attachments and need `RENDER_ATTACHMENT`.
     pub fn clear_texture(
         &self, 
         encoder: &mut wgpu::CommandEncoder,
         texture: &Texture,
     ) {
-        encoder.clear_texture(&texture.texture, &wgpu::ImageSubresourceRange::default());
+        if self.features.contains(wgpu::Features::CLEAR_TEXTURE) {
+            encoder.clear_texture(&texture.texture, &wgpu::ImageSubresourceRange::default());
+            return;
+        }
+
+        let format = texture.texture.format();
+        let usage = texture.texture.usage();
+        if format.has_depth_aspect() || format.has_stencil_aspect() {
+            assert!(
+                usage.contains(wgpu::TextureUsages::RENDER_ATTACHMENT),
+                "Ctx::clear_texture: without CLEAR_TEXTURE, depth and stencil textures must be created with RENDER_ATTACHMENT usage to be cleared"
+            );
+            self.clear_depth_stencil(encoder, &texture.texture);
+        } else {
+            assert!(
+                usage.contains(wgpu::TextureUsages::COPY_DST),
+                "Ctx::clear_texture: without CLEAR_TEXTURE, textures must be created with COPY_DST usage to be cleared"
+            );
+            self.copy_zeros_to_texture(encoder, &texture.texture);
+        }
+    }
+
+    fn clear_depth_stencil(&self, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) {
+        let format = texture.format();
+        for mip_level in 0..texture.mip_level_count() {
+            for layer in 0..texture.depth_or_array_layers() {
+                let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_mip_level: mip_level,
+                    mip_level_count: Some(1),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                });
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &view,
+                        depth_ops: format.has_depth_aspect().then_some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(0.0),
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: format.has_stencil_aspect().then_some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(0),
+                            store: wgpu::StoreOp::Store,
+                        }),
+                    }),
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                });
+            }
+        }
+    }
+
+    /// Copies from a new buffer, which wgpu zero initializes, so the clear is ordered with the encoder's other commands.
+    fn copy_zeros_to_texture(&self, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) {
+        let format = texture.format();
+        let (block_width, block_height) = format.block_dimensions();
+        let block_size = format.block_copy_size(None)
+            .expect("Ctx::clear_texture: the texture format cannot be copied to");
+        let layout = |mip_level: u32| {
+            let size = texture.size().mip_level_size(mip_level, texture.dimension()).physical_size(format);
+            let rows = size.height / block_height;
+            let bytes_per_row = (size.width / block_width * block_size).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+            (size, bytes_per_row, rows)
+        };
+
+        let (size, bytes_per_row, rows) = layout(0);
+        let zeros = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("zeros"),
+            size: bytes_per_row as u64 * rows as u64 * size.depth_or_array_layers as u64,
+            usage: wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        for mip_level in 0..texture.mip_level_count() {
+            let (size, bytes_per_row, rows) = layout(mip_level);
+            encoder.copy_buffer_to_texture(
+                wgpu::ImageCopyBuffer {
+                    buffer: &zeros,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(bytes_per_row),
+                        rows_per_image: Some(rows),
+                    },
+                },
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                size,
+            );
+        }
     }
 
     pub fn copy_buffer_to_buffer(
         &self,
         encoder: &mut wgpu::CommandEncoder,
-        src: &StorageBuffer,
-        dst: &StorageBuffer
+        src: &RawStorageBuffer,
+        dst: &RawStorageBuffer
     ) {
         assert_eq!(
             src.buffer.size(), dst.buffer.size(), 
@@ -1449,45 +2680,83 @@ impl Ctx {
         }
     }
 
-    pub fn create_timer(&self) -> GPUTimer {
+    /// Returns a timer that does nothing if timestamps are unsupported.
+    pub fn create_timer(&self) -> GPUTimer<'_> {
+        match self.try_create_timer() {
+            Ok(timer) => timer,
+            Err(e) => {
+                eprintln!("GPUTimer disabled: {}", e);
+                GPUTimer {
+                    query_set: None,
+                    query_resolution_buffer: None,
+                    timestamp_period: 0.0,
+
+                    timestamp_idx: 0,
+                    timestamp_labels: Vec::new(),
+                    pending: std::collections::VecDeque::new(),
+                    ctx: self
+                }
+            }
+        }
+    }
+
+    pub fn try_create_timer(&self) -> Result<GPUTimer<'_>, CtxError> {
+        let timer_features = wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS;
+        let missing_features = timer_features - self.features;
+        if !missing_features.is_empty() { return Err(CtxError::MissingFeatures(missing_features)) }
+
+        let timestamp_period = self.queue.get_timestamp_period();
+        if timestamp_period == 0.0 { return Err(CtxError::TimestampsUnsupported) }
+
         let query_set = self.device.create_query_set(&wgpu::QuerySetDescriptor {
             label: None,
             ty: wgpu::QueryType::Timestamp,
             count: GPUTimer::MAX_TIMESTAMP_COUNT,
         });
 
-        let timestamp_period = self.queue.get_timestamp_period();
-        if timestamp_period == 0.0 { panic!("timestamps are unsupported on this machine") }
-
         let query_resolution_buffer = self.create_storage_buffer_ex::<u64>(
             Either::B(GPUTimer::MAX_TIMESTAMP_COUNT as _),
             wgpu::BufferUsages::QUERY_RESOLVE
         );
 
-        GPUTimer {
-            query_set,
+        Ok(GPUTimer {
+            query_set: Some(query_set),
             timestamp_period,
-            query_resolution_buffer,
+            query_resolution_buffer: Some(query_resolution_buffer),
 
             timestamp_idx: 0,
             timestamp_labels: Vec::new(),
+            pending: std::collections::VecDeque::new(),
             ctx: self
-        }
+        })
     }
 }
 
 pub struct GPUTimer<'a> {
-    pub query_set: wgpu::QuerySet,
-    pub query_resolution_buffer: StorageBuffer,
+    /// These are `None` if the timer is disabled.
+    pub query_set: Option<wgpu::QuerySet>,
+    pub query_resolution_buffer: Option<StorageBuffer<u64>>,
     pub timestamp_period: f32,
 
     pub timestamp_idx: u32,
     pub timestamp_labels: Vec<&'static str>,
     pub ctx: &'a Ctx,
+    /// Timestamps copied out by `print` that have not been printed yet, oldest first.
+    pending: std::collections::VecDeque<PendingTimestamps>,
+}
+
+struct PendingTimestamps {
+    /// Taken to be mapped by the next `print`, once the encoder that copies into it has been submitted.
+    buffer: Option<std::sync::Arc<wgpu::Buffer>>,
+    size: u64,
+    ticket: Option<ReadbackTicket<u64>>,
+    labels: Vec<&'static str>,
 }
 
 impl<'a> GPUTimer<'a> {
     pub const MAX_TIMESTAMP_COUNT: u32 = 255u32;
+    /// How many frames can be waiting to be read back before `print` skips one.
+    pub const MAX_PENDING_FRAMES: usize = 4;
 
     pub fn start(&mut self, encoder: &mut wgpu::CommandEncoder) {
         self.timestamp_idx = 0;
@@ -1504,86 +2773,575 @@ impl<'a> GPUTimer<'a> {
         self.write_timestamp(encoder);
     }
 
+    /// Prints the splits without stalling, so they show up a frame or more late.
+    /// A frame is only skipped if `MAX_PENDING_FRAMES` earlier ones are still being read back.
+    ///
+    /// The timestamps are copied with `encoder` and mapped by the next call,
+    /// so `encoder` must be submitted before `print` is called again.
     pub fn print(&mut self, encoder: &mut wgpu::CommandEncoder) {
         if self.timestamp_idx as usize != self.timestamp_labels.len()+1 { 
             eprintln!("GPUTimer misuse: call start, then splits, then print");
             return;
         }
 
+        let (Some(query_set), Some(query_resolution_buffer)) = (&self.query_set, &self.query_resolution_buffer) else {
+            return;
+        };
+
+        for pending in &mut self.pending {
+            // Earlier calls' encoders have been submitted by now, so their copies can be mapped.
+            if let Some(buffer) = pending.buffer.take() {
+                let (sender, ticket) = ReadbackTicket::channel();
+                map_readback(&self.ctx.staging, buffer, pending.size, sender, |data| data.to_vec());
+                pending.ticket = Some(ticket);
+            }
+        }
+        self.ctx.device.poll(wgpu::Maintain::Poll);
+        while let Some(pending) = self.pending.front() {
+            let times = match pending.ticket.as_ref().map(|ticket| ticket.receiver.try_recv()) {
+                Some(Ok(bytes)) => Some(cast_bytes::<u64>(bytes)),
+                // The read failed, so there is nothing to print.
+                Some(Err(std::sync::mpsc::TryRecvError::Disconnected)) => None,
+                _ => break,
+            };
+            if let Some(times) = times {
+                print_times(&times, &pending.labels, self.timestamp_period);
+            }
+            self.pending.pop_front();
+        }
+        if self.pending.len() >= Self::MAX_PENDING_FRAMES {
+            return;
+        }
+
+        let size = self.timestamp_idx as u64 * std::mem::size_of::<u64>() as u64;
+        let buffer = self.ctx.staging.take(&self.ctx.device, size, wgpu::MapMode::Read);
+
         encoder.resolve_query_set(
-            &self.query_set, 
+            query_set, 
             0..self.timestamp_idx, 
-            &self.query_resolution_buffer.buffer,
+            &query_resolution_buffer.buffer,
             0
         );
+        encoder.copy_buffer_to_buffer(&query_resolution_buffer.buffer, 0, &buffer, 0, size);
 
-        let times = self.query_resolution_buffer.read_to_vec::<u64>(self.ctx);
-
-        let mut start_time = times[0];
-        let period = self.timestamp_period as f64;
-        for i in 1..(self.timestamp_idx as usize) {
-            let timestamp = times[i];
-            let label = self.timestamp_labels[i-1];
-            let t = (timestamp - start_time) as f64 * period;
-            if t > 1_000_000_000.0 {
-                println!("{}: {:3}s", label, t / 1_000_000_000.0);
-            } else if t > 1_000_000.0 {
-                println!("{}: {:3}ms", label, t / 1_000_000.0);
-            } else if t > 1_000.0 {
-                println!("{}: {:3}us", label, t / 1_000.0);
-            } else {
-                println!("{}: {:3}ns", label, t);
-            }
-            start_time = timestamp;
-        }
-    }
+        self.pending.push_back(PendingTimestamps {
+            buffer: Some(buffer),
+            size,
+            ticket: None,
+            labels: self.timestamp_labels.clone(),
+        });
+    }
 
     fn write_timestamp(&mut self, encoder: &mut wgpu::CommandEncoder) {
-        encoder.write_timestamp(&self.query_set, self.timestamp_idx);
+        if let Some(ref query_set) = self.query_set {
+            encoder.write_timestamp(query_set, self.timestamp_idx);
+        }
         self.timestamp_idx += 1;
     }
 }
 
+fn print_times(times: &[u64], labels: &[&'static str], timestamp_period: f32) {
+    let mut start_time = times[0];
+    let period = timestamp_period as f64;
+    for (&timestamp, &label) in times[1..].iter().zip(labels.iter()) {
+        let t = (timestamp - start_time) as f64 * period;
+        if t > 1_000_000_000.0 {
+            println!("{}: {:3}s", label, t / 1_000_000_000.0);
+        } else if t > 1_000_000.0 {
+            println!("{}: {:3}ms", label, t / 1_000_000.0);
+        } else if t > 1_000.0 {
+            println!("{}: {:3}us", label, t / 1_000.0);
+        } else {
+            println!("{}: {:3}ns", label, t);
+        }
+        start_time = timestamp;
+    }
+}
+
 #[derive(Debug)]
 pub struct ComputePipelineDescriptor<'a> {
     pub inputs: &'a [PipelineInput<'a>],
     pub outputs: &'a [ComputePipelineOutput<'a>],
+    /// Resources matched to the shader's declarations by name, instead of `inputs` and `outputs`.
+    pub bindings: Bindings<'a>,
+    /// `@group(1)` onwards. `@group(0)` is made from `inputs` and `outputs` or `bindings`.
+    pub groups: &'a [PipelineGroup<'a>],
     pub shader: ShaderSource,
+    /// `#define`s for the shader preprocessor. See `Ctx::preprocess_shader`.
+    pub shader_defines: &'a [(&'a str, &'a str)],
     pub shader_entry: &'static str,
-    pub dispatch_count: [u32; 3],
+    pub dispatch_count: DispatchCount,
+    /// Values for the shader's `override` declarations, by name or `@id`.
+    /// `Ctx::create_compute_pipeline_variants` builds several pipelines with different values.
+    pub constants: &'a [(&'a str, f64)],
+    /// The layout of the shader's `var<push_constant>`, e.g. `Some(Layout::new::<Params>())`.
+    /// Requires `Features::PUSH_CONSTANTS`. Set the values with `ComputePipeline::set_push_constants`.
+    pub push_constants: Option<std::alloc::Layout>,
+}
+
+/// Dispatches a single workgroup of `main` once `shader` is set.
+impl Default for ComputePipelineDescriptor<'_> {
+    fn default() -> Self {
+        ComputePipelineDescriptor {
+            inputs: &[],
+            outputs: &[],
+            bindings: Bindings::new(),
+            groups: &[],
+            shader: ShaderSource::Str(""),
+            shader_defines: &[],
+            shader_entry: "main",
+            dispatch_count: DispatchCount::Direct([1, 1, 1]),
+            constants: &[],
+            push_constants: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum DispatchCount {
+    /// Workgroups in each dimension.
+    Direct([u32; 3]),
+    /// Invocations in each dimension, divided by the shader's `@workgroup_size` and rounded up.
+    Invocations([u32; 3]),
+    /// `[x, y, z]` read from the buffer at this byte offset each time the pipeline runs,
+    /// so the GPU can size its own work. See `Ctx::create_dispatch_indirect_buffer` and `SharedBuffer`.
+    Indirect(std::sync::Arc<SharedBuffer>, u64),
+    /// An x invocation for each element in use of a storage buffer, read each time the pipeline runs. See `SharedBuffer`.
+    Elements(std::sync::Arc<SharedBuffer>),
+}
+
+impl DispatchCount {
+    /// The workgroup count, unless it is read from a buffer.
+    fn workgroups(&self, workgroup_size: [u32; 3]) -> Option<[u32; 3]> {
+        match *self {
+            DispatchCount::Direct(count) => Some(count),
+            DispatchCount::Invocations(invocations) => Some(workgroup_count(invocations, workgroup_size)),
+            DispatchCount::Elements(ref buffer) => Some(workgroup_count([buffer.latest().len(), 1, 1], workgroup_size)),
+            DispatchCount::Indirect(..) => None,
+        }
+    }
+}
+
+impl From<[u32; 3]> for DispatchCount {
+    fn from(count: [u32; 3]) -> Self { DispatchCount::Direct(count) }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub enum ShaderSource {
     Str(&'static str),
+    /// Pipelines created from a file, or that include files, can be rebuilt after they change with `reload`.
     File(&'static std::path::Path),
 }
 
 impl ShaderSource {
     pub fn to_cow(self) -> std::borrow::Cow<'static, str> {
+        match self.try_to_cow() {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn try_to_cow(self) -> Result<std::borrow::Cow<'static, str>, CtxError> {
         match self {
-            ShaderSource::Str(s) => std::borrow::Cow::Borrowed(s),
-            ShaderSource::File(p) => {
-                match std::fs::read_to_string(p) {
-                    Ok(s) => std::borrow::Cow::Owned(s),
-                    Err(e) => panic!("Could not open shader {}: {}", p.display(), e),
+            ShaderSource::Str(s) => Ok(std::borrow::Cow::Borrowed(s)),
+            ShaderSource::File(path) => match std::fs::read_to_string(path) {
+                Ok(s) => Ok(std::borrow::Cow::Owned(s)),
+                Err(error) => Err(CtxError::ShaderRead { path, error }),
+            }
+        }
+    }
+
+    /// The modification time of a `File` shader, if it can be read.
+    pub fn modified(self) -> Option<std::time::SystemTime> {
+        match self {
+            ShaderSource::Str(_) => None,
+            ShaderSource::File(path) => std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PreprocessedShader {
+    pub source: String,
+    /// Every file that was read, for hot reloading.
+    pub files: Vec<std::path::PathBuf>,
+    /// The names of every source that was expanded, including virtual includes. The first is the shader itself.
+    pub file_names: Vec<String>,
+    /// The index into `file_names` and the 1 based line that each line of `source` came from.
+    pub line_origins: Vec<(usize, usize)>,
+    /// Each line of `source` before defines were substituted, for error snippets.
+    original_lines: Vec<OriginalLine>,
+}
+
+#[derive(Clone, Debug)]
+struct OriginalLine {
+    text: String,
+    /// The byte range of each substituted define in the output line and in `text`.
+    substitutions: Vec<(std::ops::Range<usize>, std::ops::Range<usize>)>,
+}
+
+impl OriginalLine {
+    /// Maps a byte offset in the output line to `text`.
+    /// Offsets inside a substituted value map to the start of the name, or its end if `end` is set.
+    fn original_offset(&self, offset: usize, end: bool) -> usize {
+        let mut shift = 0;
+        for (output, original) in self.substitutions.iter() {
+            if offset < output.start || (end && offset == output.start) { break }
+            if offset < output.end || (end && offset == output.end) {
+                return if end { original.end } else { original.start };
+            }
+            shift = original.end as isize - output.end as isize;
+        }
+        (offset as isize + shift) as usize
+    }
+}
+
+impl PreprocessedShader {
+    /// Maps a location in `source` back to the file and line it came from.
+    fn error(&self, message: String, location: Option<wgpu::naga::SourceLocation>) -> ShaderError {
+        let Some(location) = location else {
+            return ShaderError { file: self.file_names[0].clone(), location: None, message, snippet: String::new() };
+        };
+
+        let line_idx = location.line_number as usize - 1;
+        let (file_idx, line) = self.line_origins.get(line_idx).copied().unwrap_or((0, line_idx + 1));
+        let start = location.line_position as usize - 1;
+        let end = start + location.length as usize;
+        let (text, start, end) = match self.original_lines.get(line_idx) {
+            Some(original) => (original.text.as_str(), original.original_offset(start, false), original.original_offset(end, true)),
+            None => (self.source.lines().nth(line_idx).unwrap_or(""), start, end),
+        };
+        let column = start + 1;
+        let underline = end.min(text.len()).saturating_sub(start).max(1);
+
+        ShaderError {
+            file: self.file_names[file_idx].clone(),
+            location: Some((line, column)),
+            message,
+            snippet: render_snippet(line, text, column, underline),
+        }
+    }
+}
+
+/// A shader error pointing into the original, unpreprocessed source.
+#[derive(Clone, Debug)]
+pub struct ShaderError {
+    pub file: String,
+    /// The 1 based line and column, if the error has a location.
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+    /// The offending line with the error underlined. Empty if there is no location.
+    pub snippet: String,
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.location {
+            Some((line, column)) => write!(f, "{}:{}:{}: {}\n{}", self.file, line, column, self.message, self.snippet),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+fn render_snippet(line: usize, text: &str, column: usize, underline: usize) -> String {
+    let width = line.to_string().len();
+    format!(
+        "{:width$} |\n{} | {}\n{:width$} | {}{}",
+        "", line, text, "", " ".repeat(column - 1), "^".repeat(underline),
+        width = width,
+    )
+}
+
+fn preprocess(
+    shader: ShaderSource,
+    defines: &[(&str, &str)],
+    includes: &std::collections::HashMap<&'static str, &'static str>,
+) -> Result<PreprocessedShader, CtxError> {
+    let mut preprocessor = Preprocessor {
+        includes,
+        defines: defines.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect(),
+        included: Vec::new(),
+        output: PreprocessedShader {
+            source: String::new(),
+            files: Vec::new(),
+            file_names: Vec::new(),
+            line_origins: Vec::new(),
+            original_lines: Vec::new(),
+        },
+    };
+
+    match shader {
+        ShaderSource::Str(source) => preprocessor.process("<str>", None, None, source)?,
+        ShaderSource::File(path) => {
+            let source = shader.try_to_cow()?;
+            preprocessor.output.files.push(path.to_path_buf());
+            preprocessor.process(&path.display().to_string(), path.parent(), None, &source)?;
+        }
+    }
+
+    Ok(preprocessor.output)
+}
+
+struct Preprocessor<'a> {
+    includes: &'a std::collections::HashMap<&'static str, &'static str>,
+    defines: std::collections::HashMap<String, String>,
+    included: Vec<String>,
+    output: PreprocessedShader,
+}
+
+impl<'a> Preprocessor<'a> {
+    /// `dir` is where files are included from. `virtual_dir` is set while expanding a virtual include,
+    /// to the part of its name before the last `/`.
+    fn process(
+        &mut self,
+        file: &str,
+        dir: Option<&std::path::Path>,
+        virtual_dir: Option<&str>,
+        source: &str,
+    ) -> Result<(), CtxError> {
+        let file_idx = self.output.file_names.len();
+        self.output.file_names.push(file.to_string());
+
+        let error = |line: usize, message: String| CtxError::Shader(ShaderError {
+            file: file.to_string(),
+            location: Some((line + 1, 1)),
+            message,
+            snippet: render_snippet(line + 1, source.lines().nth(line).unwrap_or(""), 1, 1),
+        });
+
+        // (whether the enclosing lines are active, whether this block is active, whether #else was seen, start line)
+        let mut conditions: Vec<(bool, bool, bool, usize)> = Vec::new();
+        let mut active = true;
+
+        for (i, line) in source.lines().enumerate() {
+            let trimmed = line.trim();
+            let Some(directive) = trimmed.strip_prefix('#') else {
+                if active { self.push_line(line, (file_idx, i + 1)); }
+                continue;
+            };
+
+            let (command, argument) = directive.split_once(char::is_whitespace).unwrap_or((directive, ""));
+            let argument = argument.trim();
+            match command {
+                "ifdef" | "ifndef" => {
+                    if argument.is_empty() { return Err(error(i, format!("#{} requires a name", command))) }
+                    let defined = self.defines.contains_key(argument);
+                    let block_active = active && (defined == (command == "ifdef"));
+                    conditions.push((active, block_active, false, i));
+                    active = block_active;
+                }
+                "else" => {
+                    let Some((outer, block_active, seen_else, _)) = conditions.last_mut() else {
+                        return Err(error(i, "#else without #ifdef".to_string()));
+                    };
+                    if *seen_else { return Err(error(i, "duplicate #else".to_string())) }
+                    *seen_else = true;
+                    *block_active = *outer && !*block_active;
+                    active = *block_active;
+                }
+                "endif" => {
+                    let Some((outer, _, _, _)) = conditions.pop() else {
+                        return Err(error(i, "#endif without #ifdef".to_string()));
+                    };
+                    active = outer;
+                }
+                _ if !active => (),
+                "define" => {
+                    let (name, value) = argument.split_once(char::is_whitespace).unwrap_or((argument, ""));
+                    if name.is_empty() { return Err(error(i, "#define requires a name".to_string())) }
+                    self.defines.insert(name.to_string(), value.trim().to_string());
+                }
+                "undef" => {
+                    self.defines.remove(argument);
+                }
+                "include" => {
+                    let Some(name) = argument.strip_prefix('"').and_then(|a| a.strip_suffix('"')) else {
+                        return Err(error(i, "expected #include \"file\"".to_string()));
+                    };
+
+                    let sibling = virtual_dir.filter(|d| !d.is_empty()).map(|d| format!("{}/{}", d, name));
+                    let virtual_include = sibling.iter()
+                        .map(String::as_str)
+                        .chain(std::iter::once(name))
+                        .find_map(|key| self.includes.get_key_value(key));
+
+                    if let Some((&key, &source)) = virtual_include {
+                        if self.mark_included(key.to_string()) {
+                            let key_dir = key.rsplit_once('/').map_or("", |(d, _)| d);
+                            self.process(key, dir, Some(key_dir), source)?;
+                        }
+                    } else {
+                        let path = dir.unwrap_or(std::path::Path::new("")).join(name);
+                        if self.mark_included(path.display().to_string()) {
+                            let source = std::fs::read_to_string(&path)
+                                .map_err(|e| error(i, format!("could not include {}: {}", path.display(), e)))?;
+                            self.output.files.push(path.clone());
+                            self.process(&path.display().to_string(), path.parent(), None, &source)?;
+                        }
+                    }
+                }
+                _ => return Err(error(i, format!("unknown directive #{}", command))),
+            }
+        }
+
+        if let Some(&(_, _, _, start)) = conditions.last() {
+            return Err(error(start, "missing #endif".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Returns false if the file was already included.
+    fn mark_included(&mut self, file: String) -> bool {
+        if self.included.contains(&file) { return false }
+        self.included.push(file);
+        true
+    }
+
+    fn push_line(&mut self, line: &str, origin: (usize, usize)) {
+        self.output.line_origins.push(origin);
+        let mut out = String::with_capacity(line.len());
+        let mut substitutions = Vec::new();
+        let mut rest = line;
+        while let Some(start) = rest.find(|c: char| c.is_alphabetic() || c == '_') {
+            let end = rest[start..].find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .map_or(rest.len(), |end| start + end);
+
+            // Skip numeric suffixes such as the u in 1u, and member accesses.
+            let in_number = rest[..start].ends_with(|c: char| c.is_ascii_digit() || c == '.');
+            let ident = &rest[start..end];
+            out.push_str(&rest[..start]);
+            match self.defines.get(ident) {
+                Some(value) if !in_number => {
+                    let original_start = line.len() - rest.len() + start;
+                    substitutions.push((out.len()..out.len() + value.len(), original_start..original_start + ident.len()));
+                    out.push_str(value);
                 }
+                _ => out.push_str(ident),
             }
+            rest = &rest[end..];
         }
+        out.push_str(rest);
+        self.output.source.push_str(&out);
+        self.output.source.push('\n');
+        self.output.original_lines.push(OriginalLine { text: line.to_string(), substitutions });
     }
 }
 
+fn latest_modified(files: &[std::path::PathBuf]) -> Option<std::time::SystemTime> {
+    files.iter()
+        .filter_map(|file| std::fs::metadata(file).and_then(|m| m.modified()).ok())
+        .max()
+}
+
+
 #[derive(Debug)]
 pub struct ComputePipeline {
     pub wgpu_pipeline: wgpu::ComputePipeline,
-    pub bind_group: wgpu::BindGroup,
-    pub layout: wgpu::PipelineLayout,
+    /// Indexed by `@group`.
+    pub bind_groups: Vec<BindGroup>,
+    /// `@group(0)` used while the pipeline's ping-pong resources are swapped.
+    pub ping_pong: Option<PingPongGroup>,
+    /// Shared by pipelines created together as variants.
+    pub layout: std::sync::Arc<wgpu::PipelineLayout>,
     pub shader: ShaderSource,
+    pub shader_defines: &'static [(&'static str, &'static str)],
+    /// The files the shader was built from and the latest time one of them was modified.
+    pub shader_files: Vec<std::path::PathBuf>,
+    pub shader_modified: Option<std::time::SystemTime>,
     pub shader_entry: &'static str,
-    pub bind_group_layout: wgpu::BindGroupLayout,
-    pub dispatch_count: [u32; 3],
+    pub constants: &'static [(&'static str, f64)],
+    /// The entry point's `@workgroup_size`, for deriving `dispatch_count` from the shader,
+    /// e.g. `buffer.dispatch_count(pipeline.workgroup_size[0])`, or use `DispatchCount::Invocations`.
+    pub workgroup_size: [u32; 3],
+    /// The offset of `base_workgroup` in the shader's push constants. See `Ctx::run_compute_pipeline_with`.
+    pub base_workgroup: Option<u32>,
+    pub dispatch_count: DispatchCount,
+    /// Set on every dispatch. See `set_push_constants`.
+    pub push_constants: Vec<u8>,
+}
+
+impl ComputePipeline {
+    /// Sets the push constants used from the next time the pipeline is run. No queue write is needed.
+    pub fn set_push_constants<T: bytemuck::NoUninit>(&mut self, data: &T) {
+        if let Err(e) = self.try_set_push_constants(data) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `set_push_constants`, but returns an error if `T` is not the size the pipeline was created with.
+    pub fn try_set_push_constants<T: bytemuck::NoUninit>(&mut self, data: &T) -> Result<(), CtxError> {
+        if std::mem::size_of::<T>() != self.push_constants.len() {
+            return Err(CtxError::Pipeline(format!(
+                "ComputePipeline::set_push_constants: expected {} bytes but got {}", self.push_constants.len(), std::mem::size_of::<T>()
+            )));
+        }
+        self.push_constants.copy_from_slice(bytemuck::bytes_of(data));
+        Ok(())
+    }
+
+    /// Rebuilds the pipeline if any of its shader files have changed, returning whether it was rebuilt.
+    /// If the new shader fails to compile, the old pipeline is kept and the error is returned.
+    ///
+    /// Every call reads the modification time of each file the shader includes,
+    /// so poll once a frame or less rather than around every dispatch.
+    pub fn reload(&mut self, ctx: &Ctx) -> Result<bool, CtxError> {
+        let reloaded = ctx.reload_shader(self.shader, self.shader_defines, &mut self.shader_files, &mut self.shader_modified)?;
+        let Some(shader) = reloaded else { return Ok(false) };
+
+        let (module, _) = ctx.validate_shader(&shader)?;
+        let base_workgroup = reflect_push_constants(&shader, &module, self.push_constants.len() as u32)?;
+        reflect_constants(&shader, &module, self.constants)?;
+        let workgroup_size = reflect_workgroup_size(&module, self.shader_entry);
+        if let Some(dispatch_count) = self.dispatch_count.workgroups(workgroup_size) {
+            ctx.check_dispatch_count(dispatch_count, base_workgroup, "ComputePipeline::reload")?;
+        }
+        ctx.limit_elements(&self.dispatch_count, workgroup_size, base_workgroup);
+        self.wgpu_pipeline = ctx.compile_shader(&shader, module, |wgpu_shader| {
+            ctx.create_wgpu_compute_pipeline(&self.layout, wgpu_shader, self.shader_entry, self.constants)
+        })?;
+        self.base_workgroup = base_workgroup;
+        self.workgroup_size = workgroup_size;
+
+        Ok(true)
+    }
+
+    /// Replaces the `@group(0)` resources without recompiling the pipeline.
+    /// They must have the same binding types as the ones the pipeline was created with.
+    pub fn rebind(&mut self, ctx: &Ctx, inputs: &[PipelineInput], outputs: &[ComputePipelineOutput]) -> Result<(), CtxError> {
+        let entries = |swapped| compute_entries(inputs, outputs, wgpu::ShaderStages::COMPUTE, swapped);
+        let group = ctx.rebind_group(
+            &self.bind_groups[0],
+            entries(false),
+            ping_pong_states(inputs, outputs, &Bindings::new()),
+            || Ok(entries(true)),
+        ).map_err(|e| CtxError::Pipeline(format!("ComputePipeline::rebind: {}", e)))?;
+        (self.bind_groups[0], self.ping_pong) = group;
+        Ok(())
+    }
+
+    /// Replaces the `@group(0)` resources of a pipeline created with `bindings`, like `rebind`.
+    /// The shader is parsed again to match the names, so `rebind` is cheaper for frequent swaps.
+    pub fn rebind_bindings(&mut self, ctx: &Ctx, bindings: &Bindings) -> Result<(), CtxError> {
+        let shader = ctx.preprocess_shader(self.shader, self.shader_defines)?;
+        let (module, info) = ctx.validate_shader(&shader)?;
+        let entry_points = [(self.shader_entry, wgpu::ShaderStages::COMPUTE)];
+        let entries = |swapped| ctx.reflect_bindings(&shader, &module, &info, bindings, &entry_points, swapped);
+        let group = ctx.rebind_group(
+            &self.bind_groups[0],
+            entries(false)?,
+            ping_pong_states(&[], &[], bindings),
+            || entries(true),
+        ).map_err(|e| CtxError::Pipeline(format!("ComputePipeline::rebind_bindings: {}", e)))?;
+        (self.bind_groups[0], self.ping_pong) = group;
+        Ok(())
+    }
 }
 
+/// The common case of `RenderPipelineDescriptorEx`.
+/// Bindings by name, extra groups and shader defines are set on `RenderPipelineDescriptorEx`.
 #[derive(Debug)]
 pub struct RenderPipelineDescriptor<'a, 'b> {
     pub inputs: &'b [PipelineInput<'b>],
@@ -1605,16 +3363,12 @@ impl<'a, 'b> From<RenderPipelineDescriptor<'a, 'b>> for RenderPipelineDescriptor
         RenderPipelineDescriptorEx {
             inputs: desc.inputs,
             vertex_buffer: Either::A(desc.vertex_buffer),
-            instance_buffer: None,
             shader: desc.shader,
             shader_vertex_entry: desc.shader_vertex_entry,
             shader_fragment_entry: desc.shader_fragment_entry,
             output_format: desc.output_format,
             draw_range,
-            blend_state: None,
-            cull_mode: None,
-            instance_range: 0..1,
-            disable_depth_test: false,
+            ..Default::default()
         }
     }
 }
@@ -1628,9 +3382,17 @@ pub enum Either<A, B> {
 #[derive(Debug)]
 pub struct RenderPipelineDescriptorEx<'a, 'b> {
     pub inputs: &'b [PipelineInput<'b>],
+    /// Resources matched to the shader's declarations by name, instead of `inputs`.
+    pub bindings: Bindings<'b>,
+    /// `@group(1)` onwards. `@group(0)` is made from `inputs` or `bindings`.
+    pub groups: &'b [PipelineGroup<'b>],
     pub vertex_buffer: Either<&'a VertexBuffer, wgpu::PrimitiveTopology>,
     pub instance_buffer: Option<&'a InstanceBuffer>,
     pub shader: ShaderSource,
+    /// `#define`s for the shader preprocessor. See `Ctx::preprocess_shader`.
+    pub shader_defines: &'b [(&'b str, &'b str)],
+    /// Values for the shader's `override` declarations, by name or `@id`.
+    pub constants: &'b [(&'b str, f64)],
     pub shader_vertex_entry: &'static str,
     pub shader_fragment_entry: &'static str,
     pub output_format: wgpu::TextureFormat,
@@ -1642,6 +3404,40 @@ pub struct RenderPipelineDescriptorEx<'a, 'b> {
     
     /// This must not be varied across pipelines in a single pass.
     pub disable_depth_test: bool,
+
+    /// Reads the draw arguments from the buffer at this byte offset instead of using `draw_range` and `instance_range`.
+    /// See `Ctx::create_draw_indirect_buffer`, or `Ctx::create_draw_indexed_indirect_buffer` if the vertex buffer has indices, and `SharedBuffer`.
+    pub draw_indirect: Option<(std::sync::Arc<SharedBuffer>, u64)>,
+
+    /// The layout of the shader's `var<push_constant>`, e.g. `Some(Layout::new::<Params>())`.
+    /// Requires `Features::PUSH_CONSTANTS`. Set the values with `RenderPipeline::set_push_constants`.
+    pub push_constants: Option<std::alloc::Layout>,
+}
+
+/// Draws nothing until `shader`, its entry points, `output_format` and `draw_range` are set.
+impl Default for RenderPipelineDescriptorEx<'_, '_> {
+    fn default() -> Self {
+        RenderPipelineDescriptorEx {
+            inputs: &[],
+            bindings: Bindings::new(),
+            groups: &[],
+            vertex_buffer: Either::B(wgpu::PrimitiveTopology::TriangleList),
+            instance_buffer: None,
+            shader: ShaderSource::Str(""),
+            shader_defines: &[],
+            constants: &[],
+            shader_vertex_entry: "vertex",
+            shader_fragment_entry: "fragment",
+            output_format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            blend_state: None,
+            cull_mode: None,
+            draw_range: 0..0,
+            instance_range: 0..1,
+            disable_depth_test: false,
+            draw_indirect: None,
+            push_constants: None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -1653,25 +3449,142 @@ pub enum DepthBuffer {
 #[derive(Debug)]
 pub struct RenderPipeline<'a> {
     pub wgpu_pipeline: wgpu::RenderPipeline,
-    pub bind_group: wgpu::BindGroup,
+    /// Indexed by `@group`.
+    pub bind_groups: Vec<BindGroup>,
+    /// `@group(0)` used while the pipeline's ping-pong resources are swapped.
+    pub ping_pong: Option<PingPongGroup>,
+    /// Shared by pipelines created together as variants.
+    pub layout: std::sync::Arc<wgpu::PipelineLayout>,
     pub vertex_buffer: Option<&'a VertexBuffer>,
     pub instance_buffer: Option<&'a InstanceBuffer>,
+    pub primitives: wgpu::PrimitiveTopology,
     pub shader: ShaderSource,
+    pub shader_defines: &'static [(&'static str, &'static str)],
+    /// The files the shader was built from and the latest time one of them was modified.
+    pub shader_files: Vec<std::path::PathBuf>,
+    pub shader_modified: Option<std::time::SystemTime>,
+    pub constants: &'static [(&'static str, f64)],
+    pub shader_vertex_entry: &'static str,
+    pub shader_fragment_entry: &'static str,
     pub disable_depth_test: bool,
     pub output_format: wgpu::TextureFormat,
+    pub blend_state: Option<wgpu::BlendState>,
+    pub cull_mode: Option<wgpu::Face>,
 
     /// these fields can be modified at runtime
     pub draw_range: std::ops::Range<u32>,
     pub instance_range: std::ops::Range<u32>,
+    /// Used instead of `draw_range` and `instance_range` if set. See `SharedBuffer`.
+    pub draw_indirect: Option<(std::sync::Arc<SharedBuffer>, u64)>,
+    /// Set on every draw. See `set_push_constants`.
+    pub push_constants: Vec<u8>,
+}
+
+impl<'a> RenderPipeline<'a> {
+    /// Sets the push constants used from the next time the pipeline is run. No queue write is needed.
+    pub fn set_push_constants<T: bytemuck::NoUninit>(&mut self, data: &T) {
+        if let Err(e) = self.try_set_push_constants(data) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `set_push_constants`, but returns an error if `T` is not the size the pipeline was created with.
+    pub fn try_set_push_constants<T: bytemuck::NoUninit>(&mut self, data: &T) -> Result<(), CtxError> {
+        if std::mem::size_of::<T>() != self.push_constants.len() {
+            return Err(CtxError::Pipeline(format!(
+                "RenderPipeline::set_push_constants: expected {} bytes but got {}", self.push_constants.len(), std::mem::size_of::<T>()
+            )));
+        }
+        self.push_constants.copy_from_slice(bytemuck::bytes_of(data));
+        Ok(())
+    }
+
+    /// Rebuilds the pipeline if any of its shader files have changed, returning whether it was rebuilt.
+    /// If the new shader fails to compile, the old pipeline is kept and the error is returned.
+    ///
+    /// Every call reads the modification time of each file the shader includes,
+    /// so poll once a frame or less rather than around every dispatch.
+    pub fn reload(&mut self, ctx: &Ctx) -> Result<bool, CtxError> {
+        let reloaded = ctx.reload_shader(self.shader, self.shader_defines, &mut self.shader_files, &mut self.shader_modified)?;
+        let Some(shader) = reloaded else { return Ok(false) };
+
+        let desc = RenderPipelineDescriptorEx {
+            inputs: &[],
+            vertex_buffer: match self.vertex_buffer {
+                Some(vbo) => Either::A(vbo),
+                None => Either::B(self.primitives),
+            },
+            instance_buffer: self.instance_buffer,
+            bindings: Bindings::new(),
+            groups: &[],
+            shader: self.shader,
+            shader_defines: self.shader_defines,
+            constants: self.constants,
+            shader_vertex_entry: self.shader_vertex_entry,
+            shader_fragment_entry: self.shader_fragment_entry,
+            output_format: self.output_format,
+            blend_state: self.blend_state,
+            cull_mode: self.cull_mode,
+            draw_range: self.draw_range.clone(),
+            instance_range: self.instance_range.clone(),
+            disable_depth_test: self.disable_depth_test,
+            draw_indirect: self.draw_indirect.clone(),
+            push_constants: None,
+        };
+
+        let (module, _) = ctx.validate_shader(&shader)?;
+        reflect_push_constants(&shader, &module, self.push_constants.len() as u32)?;
+        reflect_constants(&shader, &module, self.constants)?;
+        self.wgpu_pipeline = ctx.compile_shader(&shader, module, |wgpu_shader| {
+            ctx.create_wgpu_render_pipeline(&desc, &self.layout, wgpu_shader, self.constants)
+        })?;
+
+        Ok(true)
+    }
+
+    /// Replaces the `@group(0)` resources without recompiling the pipeline.
+    /// They must have the same binding types as the ones the pipeline was created with.
+    pub fn rebind(&mut self, ctx: &Ctx, inputs: &[PipelineInput]) -> Result<(), CtxError> {
+        let entries = |swapped| compute_entries(inputs, &[], wgpu::ShaderStages::VERTEX_FRAGMENT, swapped);
+        let group = ctx.rebind_group(
+            &self.bind_groups[0],
+            entries(false),
+            ping_pong_states(inputs, &[], &Bindings::new()),
+            || Ok(entries(true)),
+        ).map_err(|e| CtxError::Pipeline(format!("RenderPipeline::rebind: {}", e)))?;
+        (self.bind_groups[0], self.ping_pong) = group;
+        Ok(())
+    }
+
+    /// Replaces the `@group(0)` resources of a pipeline created with `bindings`, like `rebind`.
+    /// The shader is parsed again to match the names, so `rebind` is cheaper for frequent swaps.
+    pub fn rebind_bindings(&mut self, ctx: &Ctx, bindings: &Bindings) -> Result<(), CtxError> {
+        let shader = ctx.preprocess_shader(self.shader, self.shader_defines)?;
+        let (module, info) = ctx.validate_shader(&shader)?;
+        let entry_points = [(self.shader_vertex_entry, wgpu::ShaderStages::VERTEX), (self.shader_fragment_entry, wgpu::ShaderStages::FRAGMENT)];
+        let entries = |swapped| ctx.reflect_bindings(&shader, &module, &info, bindings, &entry_points, swapped);
+        let group = ctx.rebind_group(
+            &self.bind_groups[0],
+            entries(false)?,
+            ping_pong_states(&[], &[], bindings),
+            || entries(true),
+        ).map_err(|e| CtxError::Pipeline(format!("RenderPipeline::rebind_bindings: {}", e)))?;
+        (self.bind_groups[0], self.ping_pong) = group;
+        Ok(())
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub enum PipelineInput<'a> {
-    Uniform(&'a Uniform),
-    StorageBuffer(&'a StorageBuffer),
+    Uniform(&'a RawUniform),
+    StorageBuffer(&'a RawStorageBuffer),
     Texture(&'a Texture),
-    Sampler(&'a wgpu::Sampler),
+    Sampler(&'a std::sync::Arc<wgpu::Sampler>),
     StorageTexture(&'a Texture),
+    /// Binds the front buffer. See `PingPong`.
+    PingPongBuffer(&'a dyn PingPongStorage),
+    /// Binds the front texture as a storage texture. See `PingPong`.
+    PingPongTexture(&'a PingPong<Texture>),
 }
 
 impl<'a> PipelineInput<'a> {
@@ -1690,6 +3603,9 @@ impl<'a> PipelineInput<'a> {
             PipelineInput::StorageTexture(texture) => wgpu::BindingResource::TextureView(
                 &texture.view
             ),
+            PipelineInput::PingPongBuffer(_) | PipelineInput::PingPongTexture(_) => {
+                self.orient(self.ping_pong_state().unwrap().load(std::sync::atomic::Ordering::Relaxed)).binding_resource()
+            }
         }
     }
     
@@ -1716,14 +3632,48 @@ impl<'a> PipelineInput<'a> {
                 format: texture.texture.format(),
                 view_dimension: wgpu::TextureViewDimension::D2,
             },
+            PipelineInput::PingPongBuffer(_) | PipelineInput::PingPongTexture(_) => self.orient(false).binding_type(),
+        }
+    }
+
+    /// Replaces ping-pong resources with the side they bind in the given orientation.
+    fn orient(self, swapped: bool) -> Self {
+        match self {
+            PipelineInput::PingPongBuffer(ping_pong) => PipelineInput::StorageBuffer(ping_pong.raw_side(PingPongSide::Front, swapped)),
+            PipelineInput::PingPongTexture(ping_pong) => PipelineInput::StorageTexture(ping_pong.side(PingPongSide::Front, swapped)),
+            input => input,
+        }
+    }
+
+    fn group_resource(self) -> GroupResource {
+        match self {
+            PipelineInput::Uniform(uniform) => GroupResource::Buffer(uniform.buffer.clone()),
+            PipelineInput::StorageBuffer(ssbo) => GroupResource::StorageBuffer(ssbo.buffer.clone()),
+            PipelineInput::Texture(texture) | PipelineInput::StorageTexture(texture) => GroupResource::TextureView(texture.view.clone()),
+            PipelineInput::Sampler(sampler) => GroupResource::Sampler(sampler.clone()),
+            PipelineInput::PingPongBuffer(_) | PipelineInput::PingPongTexture(_) => {
+                self.orient(self.ping_pong_state().unwrap().load(std::sync::atomic::Ordering::Relaxed)).group_resource()
+            }
+        }
+    }
+
+    fn ping_pong_state(self) -> Option<&'a std::sync::Arc<std::sync::atomic::AtomicBool>> {
+        match self {
+            PipelineInput::PingPongBuffer(ping_pong) => Some(ping_pong.swap_state()),
+            PipelineInput::PingPongTexture(ping_pong) => Some(&ping_pong.swapped),
+            _ => None,
         }
     }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub enum ComputePipelineOutput<'a> {
-    StorageBuffer(&'a StorageBuffer),
+    StorageBuffer(&'a RawStorageBuffer),
     StorageTexture(&'a Texture),
+    /// Binds the back buffer. See `PingPong`.
+    PingPongBuffer(&'a dyn PingPongStorage),
+    /// Binds the back texture. See `PingPong`.
+    PingPongTexture(&'a PingPong<Texture>),
 }
 
 impl<'a> ComputePipelineOutput<'a> {
@@ -1735,6 +3685,9 @@ impl<'a> ComputePipelineOutput<'a> {
             ComputePipelineOutput::StorageTexture(texture) => wgpu::BindingResource::TextureView(
                 &texture.view
             ),
+            ComputePipelineOutput::PingPongBuffer(_) | ComputePipelineOutput::PingPongTexture(_) => {
+                self.orient(self.ping_pong_state().unwrap().load(std::sync::atomic::Ordering::Relaxed)).binding_resource()
+            }
         }
     }
 
@@ -1750,182 +3703,1344 @@ impl<'a> ComputePipelineOutput<'a> {
                 format: texture.texture.format(),
                 view_dimension: wgpu::TextureViewDimension::D2,
             },
+            ComputePipelineOutput::PingPongBuffer(_) | ComputePipelineOutput::PingPongTexture(_) => {
+                self.orient(false).binding_type()
+            }
+        }
+    }
+
+    /// Replaces ping-pong resources with the side they bind in the given orientation.
+    fn orient(self, swapped: bool) -> Self {
+        match self {
+            ComputePipelineOutput::PingPongBuffer(ping_pong) => {
+                ComputePipelineOutput::StorageBuffer(ping_pong.raw_side(PingPongSide::Back, swapped))
+            }
+            ComputePipelineOutput::PingPongTexture(ping_pong) => {
+                ComputePipelineOutput::StorageTexture(ping_pong.side(PingPongSide::Back, swapped))
+            }
+            output => output,
+        }
+    }
+
+    fn group_resource(self) -> GroupResource {
+        match self {
+            ComputePipelineOutput::StorageBuffer(ssbo) => GroupResource::StorageBuffer(ssbo.buffer.clone()),
+            ComputePipelineOutput::StorageTexture(texture) => GroupResource::TextureView(texture.view.clone()),
+            ComputePipelineOutput::PingPongBuffer(_) | ComputePipelineOutput::PingPongTexture(_) => {
+                self.orient(self.ping_pong_state().unwrap().load(std::sync::atomic::Ordering::Relaxed)).group_resource()
+            }
+        }
+    }
+
+    fn ping_pong_state(self) -> Option<&'a std::sync::Arc<std::sync::atomic::AtomicBool>> {
+        match self {
+            ComputePipelineOutput::PingPongBuffer(ping_pong) => Some(ping_pong.swap_state()),
+            ComputePipelineOutput::PingPongTexture(ping_pong) => Some(&ping_pong.swapped),
+            _ => None,
         }
     }
 }
 
+/// A resource bound by name. Its binding type is taken from the shader.
 #[derive(Copy, Clone, Debug)]
-pub enum ScalingType {
-    Nearest,
-    Linear,
+pub enum PipelineResource<'a> {
+    Uniform(&'a RawUniform),
+    StorageBuffer(&'a RawStorageBuffer),
+    Texture(&'a Texture),
+    Sampler(&'a std::sync::Arc<wgpu::Sampler>),
+    /// One side of a ping-pong buffer, e.g. `.bind("next", (&points, PingPongSide::Back))`.
+    PingPongBuffer(&'a dyn PingPongStorage, PingPongSide),
+    /// One side of a ping-pong texture.
+    PingPongTexture(&'a PingPong<Texture>, PingPongSide),
 }
 
-pub struct TextureCopierDescriptorEx<'a> {
-    pub src: &'a Texture,
-    pub dst: &'a Texture,
-    pub scaling_type: ScalingType,
-    pub clear_colour: Option<wgpu::Color>,
-}
+impl<'a> PipelineResource<'a> {
+    pub fn binding_resource(self) -> wgpu::BindingResource<'a> {
+        match self {
+            PipelineResource::Uniform(uniform) => PipelineInput::Uniform(uniform).binding_resource(),
+            PipelineResource::StorageBuffer(ssbo) => PipelineInput::StorageBuffer(ssbo).binding_resource(),
+            PipelineResource::Texture(texture) => PipelineInput::Texture(texture).binding_resource(),
+            PipelineResource::Sampler(sampler) => PipelineInput::Sampler(sampler).binding_resource(),
+            PipelineResource::PingPongBuffer(..) | PipelineResource::PingPongTexture(..) => {
+                self.orient(self.ping_pong_state().unwrap().load(std::sync::atomic::Ordering::Relaxed)).binding_resource()
+            }
+        }
+    }
 
-#[derive(Debug)]
-pub enum TextureCopier<'a> {
-    Fast {
-        src: &'a Texture,
-        dst: &'a Texture,
-    },
-    Slow {
-        pipeline: wgpu::RenderPipeline,
-        bind_group: wgpu::BindGroup,
-        dst: &'a Texture,
-        clear_colour: wgpu::Color,
-    },
-    Transparent {
-        pipeline: wgpu::RenderPipeline,
-        bind_group: wgpu::BindGroup,
-        dst: &'a Texture,
+    /// Replaces ping-pong resources with the side they bind in the given orientation.
+    fn orient(self, swapped: bool) -> Self {
+        match self {
+            PipelineResource::PingPongBuffer(ping_pong, side) => PipelineResource::StorageBuffer(ping_pong.raw_side(side, swapped)),
+            PipelineResource::PingPongTexture(ping_pong, side) => PipelineResource::Texture(ping_pong.side(side, swapped)),
+            resource => resource,
+        }
+    }
+
+    fn group_resource(self) -> GroupResource {
+        match self {
+            PipelineResource::Uniform(uniform) => PipelineInput::Uniform(uniform).group_resource(),
+            PipelineResource::StorageBuffer(ssbo) => PipelineInput::StorageBuffer(ssbo).group_resource(),
+            PipelineResource::Texture(texture) => PipelineInput::Texture(texture).group_resource(),
+            PipelineResource::Sampler(sampler) => PipelineInput::Sampler(sampler).group_resource(),
+            PipelineResource::PingPongBuffer(..) | PipelineResource::PingPongTexture(..) => {
+                self.orient(self.ping_pong_state().unwrap().load(std::sync::atomic::Ordering::Relaxed)).group_resource()
+            }
+        }
+    }
+
+    fn ping_pong_state(self) -> Option<&'a std::sync::Arc<std::sync::atomic::AtomicBool>> {
+        match self {
+            PipelineResource::PingPongBuffer(ping_pong, _) => Some(ping_pong.swap_state()),
+            PipelineResource::PingPongTexture(ping_pong, _) => Some(&ping_pong.swapped),
+            _ => None,
+        }
+    }
+
+    fn kind(self) -> &'static str {
+        match self {
+            PipelineResource::Uniform(_) => "uniform buffer",
+            PipelineResource::StorageBuffer(_) => "storage buffer",
+            PipelineResource::Texture(_) => "texture",
+            PipelineResource::Sampler(_) => "sampler",
+            PipelineResource::PingPongBuffer(..) | PipelineResource::PingPongTexture(..) => self.orient(false).kind(),
+        }
     }
 }
 
-#[derive(Debug)]
-pub struct ScreenCopier {
-    pub pipeline: wgpu::RenderPipeline,
-    pub bind_group: wgpu::BindGroup,
+impl<'a> From<&'a RawUniform> for PipelineResource<'a> {
+    fn from(uniform: &'a RawUniform) -> Self { PipelineResource::Uniform(uniform) }
 }
 
-#[derive(Debug)]
-pub struct Uniform {
-    pub buffer: wgpu::Buffer,
-    pub layout: std::alloc::Layout,
+impl<'a, T> From<&'a Uniform<T>> for PipelineResource<'a> {
+    fn from(uniform: &'a Uniform<T>) -> Self { PipelineResource::Uniform(uniform) }
 }
 
-#[derive(Debug)]
-pub struct StorageBuffer {
-    pub buffer: wgpu::Buffer,
-    pub layout: std::alloc::Layout,
+impl<'a> From<&'a RawStorageBuffer> for PipelineResource<'a> {
+    fn from(ssbo: &'a RawStorageBuffer) -> Self { PipelineResource::StorageBuffer(ssbo) }
 }
 
-#[derive(Debug)]
-pub struct Texture {
-    pub texture: wgpu::Texture,
-    pub view: wgpu::TextureView,
+impl<'a, T> From<&'a StorageBuffer<T>> for PipelineResource<'a> {
+    fn from(ssbo: &'a StorageBuffer<T>) -> Self { PipelineResource::StorageBuffer(ssbo) }
 }
 
-#[derive(Debug)]
-pub struct RenderTexture {
-    pub texture: wgpu::Texture,
-    pub view: wgpu::TextureView,
-    pub depth_texture: wgpu::Texture,
-    pub depth_view: wgpu::TextureView,
+impl<'a> From<&'a Texture> for PipelineResource<'a> {
+    fn from(texture: &'a Texture) -> Self { PipelineResource::Texture(texture) }
 }
 
-impl Uniform {
-    pub fn update<T: bytemuck::NoUninit>(&self, ctx: &Ctx, data: &T) {
-        assert_eq!(
-            std::alloc::Layout::new::<T>(),
+impl<'a> From<&'a std::sync::Arc<wgpu::Sampler>> for PipelineResource<'a> {
+    fn from(sampler: &'a std::sync::Arc<wgpu::Sampler>) -> Self { PipelineResource::Sampler(sampler) }
+}
+
+impl<'a, T: AsRef<RawStorageBuffer> + std::fmt::Debug> From<(&'a PingPong<T>, PingPongSide)> for PipelineResource<'a> {
+    fn from((ping_pong, side): (&'a PingPong<T>, PingPongSide)) -> Self { PipelineResource::PingPongBuffer(ping_pong, side) }
+}
+
+impl<'a> From<(&'a PingPong<Texture>, PingPongSide)> for PipelineResource<'a> {
+    fn from((ping_pong, side): (&'a PingPong<Texture>, PingPongSide)) -> Self { PipelineResource::PingPongTexture(ping_pong, side) }
+}
+
+/// Resources matched by name to the shader's `@group(0) @binding(n) var` declarations,
+/// e.g. `Bindings::new().bind("cells", &cells).bind("next_cells", &next_cells)`.
+/// The access mode, texture format and buffer size are checked against the shader.
+/// Declarations the pipeline's entry points don't use are skipped, and each binding is only visible
+/// to the stages that use it, so `read_write` storage works in fragment shaders.
+#[derive(Clone, Debug, Default)]
+pub struct Bindings<'a> {
+    pub entries: Vec<(&'a str, PipelineResource<'a>)>,
+}
+
+impl<'a> Bindings<'a> {
+    pub fn new() -> Self {
+        Bindings { entries: Vec::new() }
+    }
+
+    pub fn bind(mut self, name: &'a str, resource: impl Into<PipelineResource<'a>>) -> Self {
+        assert!(
+            self.entries.iter().all(|&(n, _)| n != name),
+            "Bindings::bind: `{}` is already bound", name
+        );
+        self.entries.push((name, resource.into()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// A bind group that can be shared between pipelines. Cloning shares the underlying group.
+///
+/// The group holds on to its resources, and is recreated the next time it is used
+/// after `RawStorageBuffer::resize` reallocates one of its storage buffers.
+#[derive(Clone, Debug)]
+pub struct BindGroup {
+    /// The first version. Each recreation is linked to the one it replaces, like `SharedBuffer`.
+    version: std::sync::Arc<GroupVersion>,
+    pub layout: std::sync::Arc<wgpu::BindGroupLayout>,
+    pub layout_entries: Vec<wgpu::BindGroupLayoutEntry>,
+    entries: Vec<GroupEntry>,
+}
+
+impl BindGroup {
+    /// The group for the current storage buffers, recreating it first if one of them has been reallocated.
+    pub fn bind_group(&self, ctx: &Ctx) -> &wgpu::BindGroup {
+        let mut version = &self.version;
+        while let Some(next) = version.next.get() {
+            version = next;
+        }
+        if version.is_stale() {
+            // If another thread got there first, its version is used instead.
+            let _ = version.next.set(std::sync::Arc::new(GroupVersion::new(&ctx.device, &self.layout, &self.entries)));
+            version = version.next.get().unwrap();
+        }
+        &version.bind_group
+    }
+
+    /// True if one of its storage buffers has been reallocated since the group was last used.
+    pub fn is_stale(&self) -> bool {
+        let mut version = &self.version;
+        while let Some(next) = version.next.get() {
+            version = next;
+        }
+        version.is_stale()
+    }
+}
+
+#[derive(Debug)]
+struct GroupVersion {
+    bind_group: wgpu::BindGroup,
+    /// The storage buffers `bind_group` was created with.
+    buffers: Vec<std::sync::Arc<SharedBuffer>>,
+    next: std::sync::OnceLock<std::sync::Arc<GroupVersion>>,
+}
+
+impl GroupVersion {
+    /// Creates a group for the latest version of each storage buffer in `entries`.
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, entries: &[GroupEntry]) -> Self {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &entries.iter()
+                .map(|entry| wgpu::BindGroupEntry { binding: entry.binding, resource: entry.resource.binding_resource() })
+                .collect::<Vec<_>>(),
+        });
+        let buffers = entries.iter()
+            .filter_map(|entry| match entry.resource {
+                GroupResource::StorageBuffer(ref buffer) => Some(buffer.latest().clone()),
+                _ => None,
+            })
+            .collect();
+        GroupVersion { bind_group, buffers, next: std::sync::OnceLock::new() }
+    }
+
+    fn is_stale(&self) -> bool {
+        self.buffers.iter().any(|buffer| buffer.is_replaced())
+    }
+}
+
+/// A resource in a group, held so that the group can be recreated.
+#[derive(Clone, Debug)]
+struct GroupEntry {
+    binding: u32,
+    resource: GroupResource,
+}
+
+#[derive(Clone, Debug)]
+enum GroupResource {
+    Buffer(std::sync::Arc<wgpu::Buffer>),
+    /// Bound as its latest version.
+    StorageBuffer(std::sync::Arc<SharedBuffer>),
+    TextureView(std::sync::Arc<wgpu::TextureView>),
+    Sampler(std::sync::Arc<wgpu::Sampler>),
+}
+
+impl GroupResource {
+    fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        match self {
+            GroupResource::Buffer(buffer) => wgpu::BindingResource::Buffer(
+                wgpu::BufferBinding { buffer, offset: 0, size: None }
+            ),
+            GroupResource::StorageBuffer(buffer) => wgpu::BindingResource::Buffer(
+                wgpu::BufferBinding { buffer: buffer.latest(), offset: 0, size: None }
+            ),
+            GroupResource::TextureView(view) => wgpu::BindingResource::TextureView(view),
+            GroupResource::Sampler(sampler) => wgpu::BindingResource::Sampler(sampler),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum PipelineGroup<'a> {
+    /// Creates a group used only by this pipeline.
+    Inputs(&'a [PipelineInput<'a>]),
+    /// Uses a group made with `Ctx::create_bind_group`.
+    Shared(&'a BindGroup),
+}
+
+/// A pipeline's `@group(0)` with its ping-pong resources swapped.
+#[derive(Clone, Debug)]
+pub struct PingPongGroup {
+    pub bind_group: BindGroup,
+    pub states: Vec<std::sync::Arc<std::sync::atomic::AtomicBool>>,
+}
+
+impl PingPongGroup {
+    pub fn is_swapped(&self) -> bool {
+        let swapped = self.states[0].load(std::sync::atomic::Ordering::Relaxed);
+        assert!(
+            self.states.iter().all(|state| state.load(std::sync::atomic::Ordering::Relaxed) == swapped),
+            "PingPongGroup::is_swapped: the ping-pong resources of a pipeline must be swapped together"
+        );
+        swapped
+    }
+}
+
+/// Inputs are bound first, followed by outputs. Ping-pong resources are bound in the given orientation.
+fn compute_entries(
+    inputs: &[PipelineInput],
+    outputs: &[ComputePipelineOutput],
+    visibility: wgpu::ShaderStages,
+    swapped: bool,
+) -> (Vec<wgpu::BindGroupLayoutEntry>, Vec<GroupEntry>) {
+    let input_count = inputs.len();
+    CustomChain::new(
+        inputs.iter()
+            .enumerate()
+            .map(|(i, input): (usize, &PipelineInput)| (
+                wgpu::BindGroupLayoutEntry {
+                    binding: i as u32,
+                    visibility,
+                    count: None,
+                    ty: input.binding_type(),
+                },
+                GroupEntry {
+                    binding: i as u32,
+                    resource: input.orient(swapped).group_resource(),
+                },
+            )),
+        outputs.iter()
+            .enumerate()
+            .map(|(i, output): (usize, &ComputePipelineOutput)| (
+                wgpu::BindGroupLayoutEntry {
+                    binding: (i + input_count) as u32,
+                    visibility,
+                    count: None,
+                    ty: output.binding_type(),
+                },
+                GroupEntry {
+                    binding: (i + input_count) as u32,
+                    resource: output.orient(swapped).group_resource(),
+                },
+            ))
+    ).unzip()
+}
+
+/// Checks that every constant names an `override`, and every `override` without a default is given a value.
+fn reflect_constants(shader: &PreprocessedShader, module: &wgpu::naga::Module, constants: &[(&str, f64)]) -> Result<(), ShaderError> {
+    let key = |o: &wgpu::naga::Override| o.id.map(|id| id.to_string()).or_else(|| o.name.clone()).unwrap_or_default();
+
+    if let Some(&(name, _)) = constants.iter().find(|&&(name, _)| !module.overrides.iter().any(|(_, o)| key(o) == name)) {
+        return Err(shader.error(format!("constant `{}` is not declared in the shader", name), None));
+    }
+    for (handle, o) in module.overrides.iter() {
+        if o.init.is_none() && !constants.iter().any(|&(name, _)| key(o) == name) {
+            let name = o.name.clone().unwrap_or_else(|| key(o));
+            let message = format!("override `{}` has no default so it must be given a value", name);
+            return Err(shader.error(message, Some(module.overrides.get_span(handle).location(&shader.source))));
+        }
+    }
+    Ok(())
+}
+
+/// `variant` with any of `constants` it doesn't set.
+fn merge_constants<'a>(constants: &[(&'a str, f64)], variant: &[(&'a str, f64)]) -> Vec<(&'a str, f64)> {
+    constants.iter()
+        .filter(|&&(name, _)| variant.iter().all(|&(n, _)| n != name))
+        .chain(variant)
+        .copied()
+        .collect()
+}
+
+fn constants_map(constants: &[(&str, f64)]) -> std::collections::HashMap<String, f64> {
+    constants.iter().map(|&(name, value)| (name.to_string(), value)).collect()
+}
+
+/// Finds an `override` used in `@workgroup_size`, which naga rejects with a confusing type error.
+/// Only a hint for when parsing fails, since this doesn't parse the shader.
+fn override_workgroup_size(source: &str) -> Option<(std::ops::Range<usize>, &str)> {
+    let code = blank_comments(source);
+    let overrides: Vec<&str> = identifiers(&code)
+        .zip(identifiers(&code).skip(1))
+        .filter(|&((_, keyword), _)| keyword == "override")
+        .map(|(_, (_, name))| name)
+        .collect();
+    if overrides.is_empty() { return None }
+
+    code.match_indices("@workgroup_size").find_map(|(start, attribute)| {
+        let args_start = start + attribute.len();
+        let args_end = code[args_start..].find(')').map_or(code.len(), |end| args_start + end);
+        let (offset, name) = identifiers(&code[args_start..args_end]).find(|(_, name)| overrides.contains(name))?;
+        let range = args_start + offset..args_start + offset + name.len();
+        Some((range.clone(), &source[range]))
+    })
+}
+
+/// Replaces line and block comments with spaces, keeping byte offsets.
+fn blank_comments(source: &str) -> String {
+    let mut code = source.as_bytes().to_vec();
+    let mut i = 0;
+    while i + 1 < code.len() {
+        let end = match &code[i..i + 2] {
+            b"//" => code[i..].iter().position(|&c| c == b'\n').map_or(code.len(), |end| i + end),
+            b"/*" => code[i + 2..].windows(2).position(|w| w == b"*/").map_or(code.len(), |end| i + end + 4),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        code[i..end].fill(b' ');
+        i = end;
+    }
+    // only whole comments were blanked, so any multi-byte characters left are intact
+    String::from_utf8(code).unwrap()
+}
+
+/// The byte offset of each identifier in `text`.
+fn identifiers(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut next = 0;
+    std::iter::from_fn(move || {
+        let start = next + text[next..].find(|c: char| c.is_alphabetic() || c == '_')?;
+        let end = text[start..].find(|c: char| !(c.is_alphanumeric() || c == '_')).map_or(text.len(), |end| start + end);
+        next = end;
+        Some((start, &text[start..end]))
+    })
+}
+
+fn reflect_workgroup_size(module: &wgpu::naga::Module, entry_point: &str) -> [u32; 3] {
+    module.entry_points.iter()
+        .find(|entry| entry.name == entry_point && entry.stage == wgpu::naga::ShaderStage::Compute)
+        .map_or([1; 3], |entry| entry.workgroup_size)
+}
+
+/// The workgroups needed to cover `invocations`.
+fn workgroup_count(invocations: [u32; 3], workgroup_size: [u32; 3]) -> [u32; 3] {
+    std::array::from_fn(|i| invocations[i].div_ceil(workgroup_size[i]))
+}
+
+/// Splits a dispatch into ones of at most `limit` workgroups per dimension,
+/// returning the first workgroup and the workgroup count of each.
+fn split_dispatch(dispatch_count: [u32; 3], limit: u32) -> impl Iterator<Item = ([u32; 3], [u32; 3])> {
+    let [x, y, z] = dispatch_count;
+    (0..z).step_by(limit as usize).flat_map(move |base_z| {
+        (0..y).step_by(limit as usize).flat_map(move |base_y| {
+            (0..x).step_by(limit as usize).map(move |base_x| {
+                let base = [base_x, base_y, base_z];
+                (base, std::array::from_fn(|i| (dispatch_count[i] - base[i]).min(limit)))
+            })
+        })
+    })
+}
+
+fn check_indirect_buffer(buffer: &wgpu::Buffer, offset: u64, args_size: u64, caller: &str) -> Result<(), CtxError> {
+    if !buffer.usage().contains(wgpu::BufferUsages::INDIRECT) {
+        return Err(CtxError::Pipeline(format!("{}: indirect buffers must be created with BufferUsages::INDIRECT", caller)));
+    }
+    if !offset.is_multiple_of(4) {
+        return Err(CtxError::Pipeline(format!("{}: the indirect offset must be a multiple of 4, not {}", caller, offset)));
+    }
+    if offset + args_size > buffer.size() {
+        return Err(CtxError::Pipeline(format!(
+            "{}: the indirect arguments at offset {} do not fit in a buffer of {} bytes", caller, offset, buffer.size()
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that the shader's `var<push_constant>`, if any, is `size` bytes,
+/// and returns the offset of its `base_workgroup: vec3<u32>` member if it has one.
+fn reflect_push_constants(shader: &PreprocessedShader, module: &wgpu::naga::Module, size: u32) -> Result<Option<u32>, ShaderError> {
+    use wgpu::naga::{Scalar, TypeInner, VectorSize};
+
+    for (handle, var) in module.global_variables.iter() {
+        if var.space != wgpu::naga::AddressSpace::PushConstant { continue }
+
+        let error = |message: String| shader.error(message, Some(module.global_variables.get_span(handle).location(&shader.source)));
+        let declared = module.types[var.ty].inner.size(module.to_ctx());
+        if size == 0 {
+            return Err(error("the shader declares push constants but the pipeline has none".to_string()));
+        } else if declared != size {
+            return Err(error(format!("the shader push constants are {} bytes but the pipeline's are {} bytes", declared, size)));
+        }
+
+        let TypeInner::Struct { ref members, .. } = module.types[var.ty].inner else { return Ok(None) };
+        let Some(member) = members.iter().find(|m| m.name.as_deref() == Some("base_workgroup")) else { return Ok(None) };
+        if module.types[member.ty].inner != (TypeInner::Vector { size: VectorSize::Tri, scalar: Scalar::U32 }) {
+            return Err(error("`base_workgroup` must be a vec3<u32>".to_string()));
+        }
+        return Ok(Some(member.offset));
+    }
+    Ok(None)
+}
+
+/// The distinct swap states of the ping-pong resources in a group.
+fn ping_pong_states(
+    inputs: &[PipelineInput],
+    outputs: &[ComputePipelineOutput],
+    bindings: &Bindings,
+) -> Vec<std::sync::Arc<std::sync::atomic::AtomicBool>> {
+    let mut states: Vec<std::sync::Arc<std::sync::atomic::AtomicBool>> = Vec::new();
+    let all = inputs.iter().filter_map(|input| input.ping_pong_state())
+        .chain(outputs.iter().filter_map(|output| output.ping_pong_state()))
+        .chain(bindings.entries.iter().filter_map(|(_, resource)| resource.ping_pong_state()));
+    for state in all {
+        if !states.iter().any(|s| std::sync::Arc::ptr_eq(s, state)) {
+            states.push(state.clone());
+        }
+    }
+    states
+}
+
+fn storage_texture_format(format: wgpu::naga::StorageFormat) -> wgpu::TextureFormat {
+    use wgpu::naga::StorageFormat as S;
+    use wgpu::TextureFormat as T;
+
+    match format {
+        S::R8Unorm => T::R8Unorm,
+        S::R8Snorm => T::R8Snorm,
+        S::R8Uint => T::R8Uint,
+        S::R8Sint => T::R8Sint,
+        S::R16Uint => T::R16Uint,
+        S::R16Sint => T::R16Sint,
+        S::R16Float => T::R16Float,
+        S::Rg8Unorm => T::Rg8Unorm,
+        S::Rg8Snorm => T::Rg8Snorm,
+        S::Rg8Uint => T::Rg8Uint,
+        S::Rg8Sint => T::Rg8Sint,
+        S::R32Uint => T::R32Uint,
+        S::R32Sint => T::R32Sint,
+        S::R32Float => T::R32Float,
+        S::Rg16Uint => T::Rg16Uint,
+        S::Rg16Sint => T::Rg16Sint,
+        S::Rg16Float => T::Rg16Float,
+        S::Rgba8Unorm => T::Rgba8Unorm,
+        S::Rgba8Snorm => T::Rgba8Snorm,
+        S::Rgba8Uint => T::Rgba8Uint,
+        S::Rgba8Sint => T::Rgba8Sint,
+        S::Bgra8Unorm => T::Bgra8Unorm,
+        S::Rgb10a2Uint => T::Rgb10a2Uint,
+        S::Rgb10a2Unorm => T::Rgb10a2Unorm,
+        S::Rg11b10Float => T::Rg11b10Float,
+        S::Rg32Uint => T::Rg32Uint,
+        S::Rg32Sint => T::Rg32Sint,
+        S::Rg32Float => T::Rg32Float,
+        S::Rgba16Uint => T::Rgba16Uint,
+        S::Rgba16Sint => T::Rgba16Sint,
+        S::Rgba16Float => T::Rgba16Float,
+        S::Rgba32Uint => T::Rgba32Uint,
+        S::Rgba32Sint => T::Rgba32Sint,
+        S::Rgba32Float => T::Rgba32Float,
+        S::R16Unorm => T::R16Unorm,
+        S::R16Snorm => T::R16Snorm,
+        S::Rg16Unorm => T::Rg16Unorm,
+        S::Rg16Snorm => T::Rg16Snorm,
+        S::Rgba16Unorm => T::Rgba16Unorm,
+        S::Rgba16Snorm => T::Rgba16Snorm,
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum ScalingType {
+    Nearest,
+    Linear,
+}
+
+pub struct TextureCopierDescriptorEx<'a> {
+    pub src: &'a Texture,
+    pub dst: &'a Texture,
+    pub scaling_type: ScalingType,
+    pub clear_colour: Option<wgpu::Color>,
+}
+
+#[derive(Debug)]
+pub enum TextureCopier<'a> {
+    Fast {
+        src: &'a Texture,
+        dst: &'a Texture,
+    },
+    Slow {
+        pipeline: wgpu::RenderPipeline,
+        bind_group: wgpu::BindGroup,
+        dst: &'a Texture,
+        clear_colour: wgpu::Color,
+    },
+    Transparent {
+        pipeline: wgpu::RenderPipeline,
+        bind_group: wgpu::BindGroup,
+        dst: &'a Texture,
+    }
+}
+
+#[derive(Debug)]
+pub struct ScreenCopier {
+    pub pipeline: wgpu::RenderPipeline,
+    pub bind_group: wgpu::BindGroup,
+}
+
+/// An untyped uniform buffer, checked against `layout` at runtime.
+#[derive(Debug)]
+pub struct RawUniform {
+    pub buffer: std::sync::Arc<wgpu::Buffer>,
+    pub layout: std::alloc::Layout,
+}
+
+/// An untyped storage buffer, for raw byte access and reinterpreting its contents.
+/// Element types are checked against `layout` at runtime.
+#[derive(Debug)]
+pub struct RawStorageBuffer {
+    /// Derefs to the `wgpu::Buffer`. See `SharedBuffer`.
+    pub buffer: std::sync::Arc<SharedBuffer>,
+    pub layout: std::alloc::Layout,
+}
+
+/// A storage buffer's `wgpu::Buffer`, which derefs to it.
+///
+/// `RawStorageBuffer::resize` links the replacement buffer to the one it replaces, so anything holding
+/// an older `SharedBuffer` can find the current one with `latest`. The older buffers stay alive until
+/// every holder is dropped, which at most doubles the memory since the capacity at least doubles each time.
+///
+/// Pipelines that read a storage buffer, e.g. through `DispatchCount::Indirect` or `draw_indirect`, take
+/// a clone of its `RawStorageBuffer::buffer` and use the latest buffer each time they run, so they keep
+/// working after `resize`.
+#[derive(Debug)]
+pub struct SharedBuffer {
+    buffer: wgpu::Buffer,
+    /// Elements in use, which can be fewer than the buffer holds. Only kept up to date on the latest buffer.
+    len: std::sync::atomic::AtomicU32,
+    /// The most elements `DispatchCount::Elements` pipelines without `base_workgroup` can dispatch over.
+    max_len: std::sync::atomic::AtomicU32,
+    next: std::sync::OnceLock<std::sync::Arc<SharedBuffer>>,
+}
+
+impl SharedBuffer {
+    fn new(buffer: wgpu::Buffer, len: u32) -> std::sync::Arc<Self> {
+        std::sync::Arc::new(SharedBuffer {
+            buffer,
+            len: len.into(),
+            max_len: u32::MAX.into(),
+            next: std::sync::OnceLock::new(),
+        })
+    }
+
+    fn len(&self) -> u32 {
+        self.len.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The buffer that replaced this one, or itself if it hasn't been replaced.
+    pub fn latest(self: &std::sync::Arc<Self>) -> &std::sync::Arc<SharedBuffer> {
+        let mut latest = self;
+        while let Some(next) = latest.next.get() {
+            latest = next;
+        }
+        latest
+    }
+
+    /// True if `resize` has replaced this buffer.
+    pub fn is_replaced(&self) -> bool {
+        self.next.get().is_some()
+    }
+}
+
+impl std::ops::Deref for SharedBuffer {
+    type Target = wgpu::Buffer;
+    fn deref(&self) -> &wgpu::Buffer { &self.buffer }
+}
+
+/// A uniform holding a `T`. Derefs to `RawUniform`.
+#[derive(Debug)]
+pub struct Uniform<T> {
+    pub raw: RawUniform,
+    _type: std::marker::PhantomData<T>,
+}
+
+/// A storage buffer of `T`s. Derefs to `RawStorageBuffer`.
+#[derive(Debug)]
+pub struct StorageBuffer<T> {
+    pub raw: RawStorageBuffer,
+    _type: std::marker::PhantomData<T>,
+}
+
+#[derive(Debug)]
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    /// Shared with the bind groups the texture is in.
+    pub view: std::sync::Arc<wgpu::TextureView>,
+}
+
+/// A pair of buffers or textures for iterating a simulation without copying.
+/// Pipelines read the front and write the back, and `swap` exchanges them for the next step.
+/// Only `@group(0)` follows swaps.
+#[derive(Debug)]
+pub struct PingPong<T> {
+    pub a: T,
+    pub b: T,
+    swapped: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PingPongSide {
+    Front,
+    Back,
+}
+
+impl<T> PingPong<T> {
+    /// `a` starts as the front.
+    pub fn new(a: T, b: T) -> Self {
+        PingPong { a, b, swapped: Default::default() }
+    }
+
+    /// The side pipelines currently read from, which holds the latest step after a `swap`.
+    pub fn front(&self) -> &T {
+        self.side(PingPongSide::Front, self.is_swapped())
+    }
+
+    /// The side pipelines currently write to.
+    pub fn back(&self) -> &T {
+        self.side(PingPongSide::Back, self.is_swapped())
+    }
+
+    pub fn swap(&self) {
+        self.swapped.fetch_xor(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn is_swapped(&self) -> bool {
+        self.swapped.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    fn side(&self, side: PingPongSide, swapped: bool) -> &T {
+        if (side == PingPongSide::Front) != swapped { &self.a } else { &self.b }
+    }
+}
+
+/// Lets a `PingPong` of storage buffers be bound whatever their element type.
+pub trait PingPongStorage: std::fmt::Debug {
+    fn raw_side(&self, side: PingPongSide, swapped: bool) -> &RawStorageBuffer;
+    fn swap_state(&self) -> &std::sync::Arc<std::sync::atomic::AtomicBool>;
+}
+
+impl<T: AsRef<RawStorageBuffer> + std::fmt::Debug> PingPongStorage for PingPong<T> {
+    fn raw_side(&self, side: PingPongSide, swapped: bool) -> &RawStorageBuffer {
+        self.side(side, swapped).as_ref()
+    }
+
+    fn swap_state(&self) -> &std::sync::Arc<std::sync::atomic::AtomicBool> {
+        &self.swapped
+    }
+}
+
+#[derive(Debug)]
+pub struct RenderTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub depth_texture: wgpu::Texture,
+    pub depth_view: wgpu::TextureView,
+}
+
+impl RawUniform {
+    pub fn update<T: bytemuck::NoUninit>(&self, ctx: &Ctx, data: &T) {
+        assert_eq!(
+            std::alloc::Layout::new::<T>(),
+            self.layout,
+            "RawUniform::update: Cannot update a uniform with a different type than what the Uniform was instantiated with"
+        );
+        ctx.staging.write_buffer(ctx, &self.buffer, 0, bytemuck::cast_slice(std::slice::from_ref(data)));
+    }
+}
+
+impl RawStorageBuffer {
+    pub fn update<T: bytemuck::NoUninit>(&self, ctx: &Ctx, data: &[T]) {
+        assert_eq!(
+            std::alloc::Layout::new::<T>(),
+            self.layout,
+            "RawStorageBuffer::update: Cannot update a buffer with a different type than what the buffer was instantiated with"
+        );
+        ctx.staging.write_buffer(ctx, &self.buffer, 0, bytemuck::cast_slice(data));
+    }
+
+    /// Writes `data` starting at element `offset`, leaving the rest of the buffer untouched.
+    /// The byte offset and size of `data` must be multiples of 4.
+    pub fn update_range<T: bytemuck::NoUninit>(&self, ctx: &Ctx, offset: u32, data: &[T]) {
+        assert_eq!(
+            std::alloc::Layout::new::<T>(),
+            self.layout,
+            "RawStorageBuffer::update_range: Cannot update a buffer with a different type than what the buffer was instantiated with"
+        );
+        assert!(
+            offset as u64 + data.len() as u64 <= self.len() as u64,
+            "RawStorageBuffer::update_range: {} elements at offset {} do not fit in a buffer of {} elements",
+            data.len(), offset, self.len()
+        );
+        let byte_offset = offset as u64 * self.layout.size() as u64;
+        let byte_size = std::mem::size_of_val(data) as u64;
+        assert!(
+            byte_offset.is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT) && byte_size.is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT),
+            "RawStorageBuffer::update_range: byte offset {} and size {} must be multiples of {}",
+            byte_offset, byte_size, wgpu::COPY_BUFFER_ALIGNMENT
+        );
+        ctx.staging.write_buffer(ctx, &self.buffer, byte_offset, bytemuck::cast_slice(data));
+    }
+
+    /// Returns the number of elements in the buffer, not the size in bytes.
+    pub fn len(&self) -> u32 {
+        self.buffer.len()
+    }
+
+    /// The number of elements the buffer can hold before `resize` has to reallocate it.
+    /// Shaders see the whole capacity in `arrayLength`, so pass them `len` if it matters.
+    pub fn capacity(&self) -> u32 {
+        (self.buffer.size() / self.layout.pad_to_align().size() as u64) as u32
+    }
+
+    /// Sets the number of elements in use, reallocating if it exceeds the capacity.
+    /// The capacity at least doubles, so growing one element at a time is cheap.
+    /// Without `preserve` the contents are unspecified afterwards.
+    ///
+    /// Bind groups the buffer is in are recreated the next time they are used after it is reallocated,
+    /// and indirect arguments, vertex buffers and `DispatchCount::Elements` made from `buffer` follow it,
+    /// so pipelines keep working without `rebind`.
+    ///
+    /// Fails if a `DispatchCount::Elements` pipeline without `base_workgroup` couldn't dispatch over `new_len` elements.
+    pub fn resize(&mut self, ctx: &Ctx, new_len: u32, preserve: bool) -> Result<(), CtxError> {
+        let max_len = self.buffer.max_len.load(std::sync::atomic::Ordering::Relaxed);
+        if new_len > max_len {
+            return Err(CtxError::Pipeline(format!(
+                "RawStorageBuffer::resize: {} elements exceeds the {} a pipeline dispatching over them can run. \
+                Splitting the dispatch needs a `base_workgroup: vec3<u32>` member in the shader's push constants",
+                new_len, max_len
+            )));
+        }
+        if new_len > self.capacity() {
+            let capacity = new_len.max(self.capacity().saturating_mul(2));
+            let buffer = ctx.create_buffer_with_capacity(capacity, self.layout, self.buffer.usage());
+
+            let used = (self.len() as u64 * self.layout.pad_to_align().size() as u64)
+                .next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+                .min(self.buffer.size());
+            if preserve && used > 0 {
+                let mut encoder = ctx.device.create_command_encoder(&Default::default());
+                encoder.copy_buffer_to_buffer(&self.buffer, 0, &buffer, 0, used);
+                ctx.queue.submit(std::iter::once(encoder.finish()));
+            }
+
+            let buffer = SharedBuffer::new(buffer, new_len);
+            buffer.max_len.store(max_len, std::sync::atomic::Ordering::Relaxed);
+            let _ = self.buffer.next.set(buffer.clone());
+            self.buffer = buffer;
+        }
+        self.buffer.len.store(new_len, std::sync::atomic::Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Appends `data`, growing the buffer if needed. See `resize`.
+    pub fn extend<T: bytemuck::NoUninit>(&mut self, ctx: &Ctx, data: &[T]) -> Result<(), CtxError> {
+        assert_eq!(
+            std::alloc::Layout::new::<T>(),
             self.layout,
-            "Uniform::update: Cannot update a uniform with a different type than what the Uniform was instantiated with"
+            "RawStorageBuffer::extend: Cannot extend a buffer with a different type than what the buffer was instantiated with"
+        );
+        let offset = self.len();
+        let new_len = u32::try_from(data.len()).ok().and_then(|len| offset.checked_add(len));
+        let Some(new_len) = new_len else {
+            panic!("RawStorageBuffer::extend: {} elements would overflow the length of {}", data.len(), offset)
+        };
+        self.resize(ctx, new_len, true)?;
+        self.update_range(ctx, offset, data);
+        Ok(())
+    }
+
+    pub fn push<T: bytemuck::NoUninit>(&mut self, ctx: &Ctx, value: T) -> Result<(), CtxError> {
+        self.extend(ctx, std::slice::from_ref(&value))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn dispatch_count(&self, workgroup_size: u32) -> [u32; 3] {
+        assert_ne!(
+            workgroup_size,
+            0,
+            "RawStorageBuffer::dispatch_count: workgroup_size cannot be zero"
+        );
+        [self.len().div_ceil(workgroup_size), 1, 1]
+    }
+
+    /// Panics if `T` has a different layout that the buffer's type.
+    pub fn read_to_vec<T: bytemuck::Pod>(&self, ctx: &Ctx) -> Vec<T> {
+        assert_eq!(
+            std::alloc::Layout::new::<T>(),
+            self.layout,
+            "RawStorageBuffer::read_to_vec: Cannot read a buffer into a Vec with a different type"
+        );
+        self.read_range(ctx, 0..self.len())
+    }
+
+    /// Reads the elements in `range`, copying only that slice of the buffer.
+    /// Panics if `T` has a different layout that the buffer's type.
+    pub fn read_range<T: bytemuck::Pod>(&self, ctx: &Ctx, range: std::ops::Range<u32>) -> Vec<T> {
+        assert_eq!(
+            std::alloc::Layout::new::<T>(),
+            self.layout,
+            "RawStorageBuffer::read_range: Cannot read a buffer into a Vec with a different type"
+        );
+        self.read_range_async(ctx, range).wait(ctx)
+    }
+
+    pub fn read_to_vec_bytes(&self, ctx: &Ctx) -> Vec<u8> {
+        self.read_range_bytes(ctx, 0..self.len())
+    }
+
+    /// Reads the bytes of the elements in `range`.
+    pub fn read_range_bytes(&self, ctx: &Ctx, range: std::ops::Range<u32>) -> Vec<u8> {
+        let (sender, ticket) = ReadbackTicket::channel();
+        self.start_read(ctx, self.byte_range(range, "RawStorageBuffer::read_range_bytes"), sender);
+        ticket.wait(ctx)
+    }
+
+    /// Blocks until the buffer has been sent.
+    #[deprecated(note = "blocks until the GPU is idle; use `read_async` and poll the ticket instead")]
+    pub fn read(&self, ctx: &Ctx, sender: std::sync::mpsc::Sender<Vec<u8>>) {
+        self.start_read(ctx, 0..self.buffer.size(), sender);
+        ctx.device.poll(wgpu::Maintain::Wait);
+    }
+
+    /// Starts reading the buffer without blocking. Poll the ticket once per frame.
+    pub fn read_async<T: bytemuck::Pod>(&self, ctx: &Ctx) -> ReadbackTicket<T> {
+        self.read_range_async(ctx, 0..self.len())
+    }
+
+    /// Starts reading the elements in `range` without blocking.
+    /// Panics if `T` has a different layout that the buffer's type.
+    pub fn read_range_async<T: bytemuck::Pod>(&self, ctx: &Ctx, range: std::ops::Range<u32>) -> ReadbackTicket<T> {
+        assert_eq!(
+            std::alloc::Layout::new::<T>(),
+            self.layout,
+            "RawStorageBuffer::read_range_async: Cannot read a buffer into a Vec with a different type"
+        );
+        let (sender, ticket) = ReadbackTicket::channel();
+        self.start_read(ctx, self.byte_range(range, "RawStorageBuffer::read_range_async"), sender);
+        ticket
+    }
+
+    fn byte_range(&self, range: std::ops::Range<u32>, caller: &str) -> std::ops::Range<u64> {
+        match byte_range(range.clone(), self.len(), self.layout.size()) {
+            Some(bytes) => bytes,
+            None => panic!("{}: range {:?} is out of bounds for a buffer of {} elements", caller, range, self.len()),
+        }
+    }
+
+    /// Copies `range` out through a mappable buffer, widened to the copy alignment
+    /// and trimmed again once mapped. Nothing is sent until the device is polled.
+    fn start_read(&self, ctx: &Ctx, range: std::ops::Range<u64>, sender: std::sync::mpsc::Sender<Vec<u8>>) {
+        let (std::ops::Range { start, end }, trim) = copy_range(range, self.buffer.size());
+
+        if start == end {
+            if let Err(e) = sender.send(Vec::new()) {
+                eprintln!("buffer data send failed: {}", e);
+            }
+            return;
+        }
+
+        let intermediate_buffer = ctx.staging.take(&ctx.device, end - start, wgpu::MapMode::Read);
+
+        let mut encoder = ctx.device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(&self.buffer, start, &intermediate_buffer, 0, end - start);
+        ctx.queue.submit(std::iter::once(encoder.finish()));
+
+        map_readback(&ctx.staging, intermediate_buffer, end - start, sender, move |data| data[trim].to_vec());
+    }
+}
+
+/// The bytes of the elements in `range`, if it is within `len` elements.
+fn byte_range(range: std::ops::Range<u32>, len: u32, element_size: usize) -> Option<std::ops::Range<u64>> {
+    if range.start > range.end || range.end > len {
+        return None;
+    }
+    let size = element_size as u64;
+    Some(range.start as u64 * size..range.end as u64 * size)
+}
+
+/// Widens `range` to the copy alignment, clamped to `buffer_size`,
+/// and returns it with the part of the copy that is `range`.
+fn copy_range(range: std::ops::Range<u64>, buffer_size: u64) -> (std::ops::Range<u64>, std::ops::Range<usize>) {
+    let align = wgpu::COPY_BUFFER_ALIGNMENT;
+    let start = range.start - range.start % align;
+    let end = range.end.next_multiple_of(align).min(buffer_size);
+    (start..end, (range.start - start) as usize..(range.end - start) as usize)
+}
+
+/// Maps the first `size` bytes of a staging `buffer` once the GPU is done with it,
+/// sends `unpack` of them, and returns the buffer to `pool`.
+/// The callback only runs when the device is polled.
+fn map_readback(
+    pool: &StagingPool,
+    buffer: std::sync::Arc<wgpu::Buffer>,
+    size: u64,
+    sender: std::sync::mpsc::Sender<Vec<u8>>,
+    unpack: impl FnOnce(&[u8]) -> Vec<u8> + Send + 'static,
+) {
+    let callback_buffer = buffer.clone();
+    let pool = pool.clone();
+
+    buffer.slice(..size)
+        .map_async(
+            wgpu::MapMode::Read,
+            move |res| {
+                match res {
+                    Ok(_) => (),
+                    Err(_) => {
+                        eprintln!("buffer read failed");
+                        pool.lost();
+                        return;
+                    }
+                };
+
+                let data = unpack(&callback_buffer.slice(..size).get_mapped_range());
+                callback_buffer.unmap();
+                pool.give_back(callback_buffer, wgpu::MapMode::Read);
+
+                match sender.send(data) {
+                    Ok(_) => (),
+                    Err(e) => eprintln!("buffer data send failed: {}", e),
+                }
+            }
         );
-        ctx.queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(std::slice::from_ref(data)));
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StagingStats {
+    /// Staging buffers created because none of the right size class was free.
+    pub allocations: u64,
+    /// Requests served by a recycled buffer.
+    pub reuses: u64,
+    /// Free buffers dropped to stay under `StagingPool::set_max_free_bytes`, or by `clear`.
+    pub evictions: u64,
+    /// Buffers waiting on the GPU.
+    pub in_flight: u64,
+    pub free_buffers: u64,
+    pub free_bytes: u64,
+}
+
+/// Mappable buffers for uploads and readbacks, recycled by size class.
+/// Readback buffers return once their data has been read, upload buffers once they
+/// are mapped again after their copy. The least recently returned free buffers are
+/// dropped once they add up to more than `set_max_free_bytes`.
+#[derive(Clone, Default)]
+pub struct StagingPool {
+    inner: std::sync::Arc<std::sync::Mutex<StagingPoolInner>>,
+}
+
+/// The free buffers and stats, generic over the buffer so the bookkeeping can be tested without a device.
+struct StagingPoolInner<B = std::sync::Arc<wgpu::Buffer>> {
+    /// Free buffers by size class, with the order they were returned in.
+    read: std::collections::HashMap<u64, Vec<(u64, B)>>,
+    write: std::collections::HashMap<u64, Vec<(u64, B)>>,
+    returned: u64,
+    max_free_bytes: u64,
+    stats: StagingStats,
+}
+
+impl<B> Default for StagingPoolInner<B> {
+    fn default() -> Self {
+        StagingPoolInner {
+            read: std::collections::HashMap::new(),
+            write: std::collections::HashMap::new(),
+            returned: 0,
+            max_free_bytes: StagingPool::DEFAULT_MAX_FREE_BYTES,
+            stats: StagingStats::default(),
+        }
+    }
+}
+
+impl<B> StagingPoolInner<B> {
+    fn free_lists(&mut self, mode: wgpu::MapMode) -> &mut std::collections::HashMap<u64, Vec<(u64, B)>> {
+        match mode {
+            wgpu::MapMode::Read => &mut self.read,
+            wgpu::MapMode::Write => &mut self.write,
+        }
+    }
+
+    /// A free buffer of `class`, if there is one. Either way the caller has a buffer in flight.
+    fn take(&mut self, class: u64, mode: wgpu::MapMode) -> Option<B> {
+        let free = self.free_lists(mode).get_mut(&class).and_then(Vec::pop);
+        self.stats.in_flight += 1;
+        match free {
+            Some((_, buffer)) => {
+                self.stats.reuses += 1;
+                self.stats.free_buffers -= 1;
+                self.stats.free_bytes -= class;
+                Some(buffer)
+            }
+            None => {
+                self.stats.allocations += 1;
+                None
+            }
+        }
+    }
+
+    fn give_back(&mut self, buffer: B, class: u64, mode: wgpu::MapMode) {
+        self.returned += 1;
+        let returned = self.returned;
+        self.free_lists(mode).entry(class).or_default().push((returned, buffer));
+        self.stats.in_flight -= 1;
+        self.stats.free_buffers += 1;
+        self.stats.free_bytes += class;
+        self.evict(self.max_free_bytes);
     }
+
+    /// Drops the least recently returned free buffers until they take up at most `max_free_bytes`.
+    fn evict(&mut self, max_free_bytes: u64) {
+        while self.stats.free_bytes > max_free_bytes {
+            let oldest = [wgpu::MapMode::Read, wgpu::MapMode::Write].into_iter()
+                .flat_map(|mode| {
+                    let lists = match mode {
+                        wgpu::MapMode::Read => &self.read,
+                        wgpu::MapMode::Write => &self.write,
+                    };
+                    lists.iter().filter_map(move |(&class, list)| Some((list.first()?.0, class, mode)))
+                })
+                .min_by_key(|&(returned, _, _)| returned);
+            let Some((_, class, mode)) = oldest else { break };
+
+            self.free_lists(mode).get_mut(&class).unwrap().remove(0);
+            self.stats.evictions += 1;
+            self.stats.free_buffers -= 1;
+            self.stats.free_bytes -= class;
+        }
+    }
+}
+
+impl StagingPool {
+    const MIN_SIZE_CLASS: u64 = 256;
+    pub const DEFAULT_MAX_FREE_BYTES: u64 = 256 << 20;
+    /// Smaller writes go through `Queue::write_buffer`, which batches them into the next submit.
+    const MIN_STAGED_WRITE: u64 = 64 << 10;
+
+    pub fn stats(&self) -> StagingStats {
+        self.lock().stats
+    }
+
+    /// Limits the memory kept in free buffers, dropping the least recently used ones now if needed.
+    pub fn set_max_free_bytes(&self, max_free_bytes: u64) {
+        let mut inner = self.lock();
+        inner.max_free_bytes = max_free_bytes;
+        inner.evict(max_free_bytes);
+    }
+
+    /// Frees the buffers that are not in use.
+    pub fn clear(&self) {
+        self.lock().evict(0);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, StagingPoolInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sixteen classes per power of two, so at most an eighth of a buffer goes unused.
+    fn size_class(size: u64) -> u64 {
+        if size <= Self::MIN_SIZE_CLASS {
+            return Self::MIN_SIZE_CLASS;
+        }
+        size.next_multiple_of(size.next_power_of_two() / 16)
+    }
+
+    /// Read buffers are returned unmapped, write buffers mapped.
+    fn take(&self, device: &wgpu::Device, size: u64, mode: wgpu::MapMode) -> std::sync::Arc<wgpu::Buffer> {
+        let class = Self::size_class(size);
+        if let Some(buffer) = self.lock().take(class, mode) {
+            return buffer;
+        }
+
+        std::sync::Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size: class,
+            usage: match mode {
+                wgpu::MapMode::Read => wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                wgpu::MapMode::Write => wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+            },
+            mapped_at_creation: mode == wgpu::MapMode::Write,
+        }))
+    }
+
+    fn give_back(&self, buffer: std::sync::Arc<wgpu::Buffer>, mode: wgpu::MapMode) {
+        let class = buffer.size();
+        self.lock().give_back(buffer, class, mode);
+    }
+
+    /// An in flight buffer failed to map and is dropped.
+    fn lost(&self) {
+        self.lock().stats.in_flight -= 1;
+    }
+
+    /// Copies `data` into `dst` through a staging buffer instead of `Queue::write_buffer`
+    /// if it is large. Either way the data lands before the next submitted commands run.
+    fn write_buffer(&self, ctx: &Ctx, dst: &wgpu::Buffer, offset: u64, data: &[u8]) {
+        let size = data.len() as u64;
+        // Misaligned writes are left for wgpu to report.
+        if size < Self::MIN_STAGED_WRITE || !size.is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT) {
+            ctx.queue.write_buffer(dst, offset, data);
+            return;
+        }
+
+        let buffer = self.take(&ctx.device, size, wgpu::MapMode::Write);
+        buffer.slice(..size).get_mapped_range_mut().copy_from_slice(data);
+        buffer.unmap();
+
+        let mut encoder = ctx.device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(&buffer, 0, dst, offset, size);
+        ctx.queue.submit(std::iter::once(encoder.finish()));
+
+        let callback_buffer = buffer.clone();
+        let pool = self.clone();
+        buffer.slice(..).map_async(wgpu::MapMode::Write, move |res| match res {
+            Ok(_) => pool.give_back(callback_buffer, wgpu::MapMode::Write),
+            Err(_) => pool.lost(),
+        });
+    }
+}
+
+/// A readback that has been started but not waited on.
+/// Poll it once per frame instead of stalling until the GPU catches up.
+#[derive(Debug)]
+pub struct ReadbackTicket<T> {
+    receiver: std::sync::mpsc::Receiver<Vec<u8>>,
+    _type: std::marker::PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> ReadbackTicket<T> {
+    fn channel() -> (std::sync::mpsc::Sender<Vec<u8>>, Self) {
+        let (sender, receiver) = std::sync::mpsc::channel::<Vec<u8>>();
+        (sender, ReadbackTicket { receiver, _type: std::marker::PhantomData })
+    }
+
+    /// Polls the device without blocking, and returns the data the first time it has arrived.
+    /// Returns `None` forever if the read failed.
+    pub fn poll(&self, ctx: &Ctx) -> Option<Vec<T>> {
+        ctx.device.poll(wgpu::Maintain::Poll);
+        self.receiver.try_recv().ok().map(cast_bytes)
+    }
+
+    /// Blocks until the data has arrived.
+    pub fn wait(self, ctx: &Ctx) -> Vec<T> {
+        ctx.device.poll(wgpu::Maintain::Wait);
+        match self.receiver.recv() {
+            Ok(buf) => cast_bytes(buf),
+            Err(e) => panic!("reading data buffer failed: {}", e),
+        }
+    }
+}
+
+/// Copies `bytes` into a correctly aligned `Vec<T>`.
+fn cast_bytes<T: bytemuck::Pod>(bytes: Vec<u8>) -> Vec<T> {
+    let size = std::mem::size_of::<T>();
+    assert!(bytes.len().is_multiple_of(size), "Error satisfying type size and alignment");
+    let mut vec = vec![T::zeroed(); bytes.len() / size];
+    bytemuck::cast_slice_mut::<T, u8>(&mut vec).copy_from_slice(&bytes);
+    vec
+}
+
+impl<T: bytemuck::NoUninit> Uniform<T> {
+    /// Panics if `raw` was not created for a `T`.
+    pub fn from_raw(raw: RawUniform) -> Self {
+        assert_eq!(
+            std::alloc::Layout::new::<T>(),
+            raw.layout,
+            "Uniform::from_raw: the uniform was created with a different type"
+        );
+        Uniform { raw, _type: std::marker::PhantomData }
+    }
+
+    pub fn into_raw(self) -> RawUniform {
+        self.raw
+    }
+
+    pub fn update(&self, ctx: &Ctx, data: &T) {
+        ctx.staging.write_buffer(ctx, &self.raw.buffer, 0, bytemuck::bytes_of(data));
+    }
+}
+
+impl<T> std::ops::Deref for Uniform<T> {
+    type Target = RawUniform;
+    fn deref(&self) -> &RawUniform { &self.raw }
 }
 
-impl StorageBuffer {
-    pub fn update<T: bytemuck::NoUninit>(&self, ctx: &Ctx, data: &[T]) {
+impl<T: bytemuck::NoUninit> StorageBuffer<T> {
+    /// Panics if `raw` was not created for `T`s.
+    pub fn from_raw(raw: RawStorageBuffer) -> Self {
         assert_eq!(
             std::alloc::Layout::new::<T>(),
-            self.layout,
-            "StorageBuffer::update: Cannot update a buffer with a different type than what the buffer was instantiated with"
+            raw.layout,
+            "StorageBuffer::from_raw: the buffer was created with a different type"
         );
-        ctx.queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(data));
+        StorageBuffer { raw, _type: std::marker::PhantomData }
     }
 
-    /// Returns the number of elements in the buffer, not the size in bytes.
-    pub fn len(&self) -> u32 {
-        (self.buffer.size() / self.layout.pad_to_align().size() as u64) as u32
+    pub fn into_raw(self) -> RawStorageBuffer {
+        self.raw
     }
 
-    pub fn dispatch_count(&self, workgroup_size: u32) -> [u32; 3] {
-        assert_ne!(
-            workgroup_size,
-            0,
-            "StorageBuffer::dispatch: workgroup_size cannot be zero"
-        );
-        [(self.len() + workgroup_size-1)/workgroup_size, 1, 1]
+    pub fn update(&self, ctx: &Ctx, data: &[T]) {
+        ctx.staging.write_buffer(ctx, &self.raw.buffer, 0, bytemuck::cast_slice(data));
     }
 
-    /// Panics if `T` has a different layout that the buffer's type,
-    /// or the buffer size and capacity are not a multiples of `T`'s size.
-    pub fn read_to_vec<T: bytemuck::Pod>(&self, ctx: &Ctx) -> Vec<T> {
-        assert_eq!(
-            std::alloc::Layout::new::<T>(),
-            self.layout,
-            "StorageBuffer::read_to_vec: Cannot read a buffer into a Vec with a different type"
-        );
-
-        let buf_u8 = self.read_to_vec_bytes(ctx);
+    /// Writes `data` starting at element `offset`.
+    pub fn update_range(&self, ctx: &Ctx, offset: u32, data: &[T]) {
+        self.raw.update_range(ctx, offset, data);
+    }
 
-        let size_t = std::alloc::Layout::new::<T>().pad_to_align().size();
-        assert_eq!(buf_u8.len() % size_t, 0, "Error satisfying type size and alignment");
-        assert_eq!(buf_u8.capacity() % size_t, 0, "Error satisfying type size and alignment");
+    /// See `RawStorageBuffer::resize`.
+    pub fn resize(&mut self, ctx: &Ctx, new_len: u32, preserve: bool) -> Result<(), CtxError> {
+        self.raw.resize(ctx, new_len, preserve)
+    }
 
-        unsafe {
-            // Ensure the original vector is not dropped.
-            let mut buf = std::mem::ManuallyDrop::new(buf_u8);
-            Vec::from_raw_parts(buf.as_mut_ptr() as *mut T,
-                                buf.len() / size_t,
-                                buf.capacity() / size_t)
-        }
+    /// Appends `data`, growing the buffer if needed.
+    pub fn extend(&mut self, ctx: &Ctx, data: &[T]) -> Result<(), CtxError> {
+        self.raw.extend(ctx, data)
     }
 
-    pub fn read_to_vec_bytes(&self, ctx: &Ctx) -> Vec<u8> {
-        let (sender, receiver) = std::sync::mpsc::channel::<Vec<u8>>();
-        self.read(ctx, sender);
-        match receiver.recv() {
-            Ok(buf) => buf,
-            Err(e) => panic!("reading data buffer failed: {}", e),
-        }
+    pub fn push(&mut self, ctx: &Ctx, value: T) -> Result<(), CtxError> {
+        self.raw.push(ctx, value)
     }
 
-    pub fn read(&self, ctx: &Ctx, sender: std::sync::mpsc::Sender<Vec<u8>>) {
-        let size = self.buffer.size();
+    pub fn read_to_vec(&self, ctx: &Ctx) -> Vec<T> where T: bytemuck::Pod {
+        self.raw.read_to_vec(ctx)
+    }
 
-        let intermediate_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size,
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+    /// Reads the elements in `range`, copying only that slice of the buffer.
+    pub fn read_range(&self, ctx: &Ctx, range: std::ops::Range<u32>) -> Vec<T> where T: bytemuck::Pod {
+        self.raw.read_range(ctx, range)
+    }
 
-        let mut encoder = ctx.device.create_command_encoder(&Default::default());
-        encoder.copy_buffer_to_buffer(&self.buffer, 0, &intermediate_buffer, 0, size);
-        ctx.queue.submit(std::iter::once(encoder.finish()));
+    /// Starts reading the buffer without blocking. See `ReadbackTicket`.
+    pub fn read_async(&self, ctx: &Ctx) -> ReadbackTicket<T> where T: bytemuck::Pod {
+        self.raw.read_async(ctx)
+    }
 
-        let arc_buffer = std::sync::Arc::new(intermediate_buffer);
-        let callback_buffer = arc_buffer.clone();
-
-        arc_buffer.slice(..)
-            .map_async(
-                wgpu::MapMode::Read,
-                move |res| {
-                    match res {
-                        Ok(_) => (),
-                        Err(_) => {
-                            eprintln!("buffer read failed");
-                            return;
-                        }
-                    };
+    pub fn read_range_async(&self, ctx: &Ctx, range: std::ops::Range<u32>) -> ReadbackTicket<T> where T: bytemuck::Pod {
+        self.raw.read_range_async(ctx, range)
+    }
+}
 
-                    let data = callback_buffer.slice(..).get_mapped_range().to_vec();
+impl<T> std::ops::Deref for StorageBuffer<T> {
+    type Target = RawStorageBuffer;
+    fn deref(&self) -> &RawStorageBuffer { &self.raw }
+}
 
-                    match sender.send(data) {
-                        Ok(_) => (),
-                        Err(e) => eprintln!("buffer data send failed: {}", e),
-                    }
-                }
-            );
+impl<T> AsRef<RawStorageBuffer> for StorageBuffer<T> {
+    fn as_ref(&self) -> &RawStorageBuffer { &self.raw }
+}
 
-        ctx.device.poll(wgpu::Maintain::Wait);
-    }
+impl AsRef<RawStorageBuffer> for RawStorageBuffer {
+    fn as_ref(&self) -> &RawStorageBuffer { self }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -1967,7 +5082,8 @@ pub struct IndexBuffer {
 
 #[derive(Debug)]
 pub struct VertexBuffer {
-    pub vertex_buffer: wgpu::Buffer,
+    /// Can be a storage buffer's `buffer.clone()`, to draw what a compute shader wrote. See `SharedBuffer`.
+    pub vertex_buffer: std::sync::Arc<SharedBuffer>,
     pub vertex_layout: wgpu::VertexBufferLayout<'static>,
     pub vertex_count: u32,
     pub index_buffer: Option<IndexBuffer>,
@@ -2050,12 +5166,23 @@ impl Texture {
     #[cfg(feature = "images")]
     pub fn read_to_png(&self, ctx: &Ctx, file: &std::path::Path) {
         let buf = self.read_to_vec(ctx);
+        self.write_png(&buf, file);
+    }
+
+    /// Writes `data` read back from this texture, e.g. by `read_async`, to a png.
+    /// The texture format must be Rgba8Unorm or Bgra8Unorm, or their srgb variants.
+    #[cfg(feature = "images")]
+    pub fn write_png(&self, data: &[u8], file: &std::path::Path) {
+        assert!(
+            matches!(self.texture.format().remove_srgb_suffix(), wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Bgra8Unorm),
+            "Texture::write_png: the format must be Rgba8Unorm or Bgra8Unorm, or their srgb variants"
+        );
         let width = self.texture.width();
         let height = self.texture.height();
 
         lodepng::encode_file(
             file,
-            &buf,
+            data,
             width as usize,
             height as usize,
             lodepng::ColorType::RGBA,
@@ -2063,114 +5190,423 @@ impl Texture {
         ).unwrap();
     }
 
+    /// Reads tightly packed rows of texels as bytes. See `read_to_vec_as` for other types.
     pub fn read_to_vec(&self, ctx: &Ctx) -> Vec<u8> {
-        let (sender, receiver) = std::sync::mpsc::channel::<Vec<u8>>();
-        self.read(ctx, sender);
-        match receiver.recv() {
-            Ok(buf) => buf,
-            Err(e) => panic!("reading data buffer failed: {}", e),
-        }
+        self.read_to_vec_as(ctx)
+    }
+
+    /// Reads tightly packed rows of texels. `T` is the texel type or evenly divides it,
+    /// e.g. `[f32; 2]` or `f32` for Rg32Float, and `u8` for any format.
+    pub fn read_to_vec_as<T: bytemuck::Pod>(&self, ctx: &Ctx) -> Vec<T> {
+        self.read_async(ctx).wait(ctx)
     }
 
-    /// texture format must be Rgba8Unorm or Bgra8Unorm, or their srgb variants.
+    /// Sends tightly packed rows of texels. Depth textures send their depth aspect.
+    /// Blocks until the texture has been sent.
+    #[deprecated(note = "blocks until the GPU is idle; use `read_async` and poll the ticket instead")]
     pub fn read(&self, ctx: &Ctx, sender: std::sync::mpsc::Sender<Vec<u8>>) {
-        let width = self.texture.width();
-        let height = self.texture.height();
+        start_texture_read(ctx, &self.texture, sender);
+        ctx.device.poll(wgpu::Maintain::Wait);
+    }
 
-        let format = self.texture.format();
-        assert!(
-            format == wgpu::TextureFormat::Bgra8Unorm 
-            || format == wgpu::TextureFormat::Bgra8UnormSrgb 
-            || format == wgpu::TextureFormat::Rgba8Unorm
-            || format == wgpu::TextureFormat::Rgba8UnormSrgb,
-            "Texture::read: to read a texture, the format must be: Bgra8Unorm, Bgra8UnormSrgb, Rgba8Unorm, or Rgba8UnormSrgb"
-        );
+    /// Starts reading the texture without blocking. See `read_to_vec` and `ReadbackTicket`.
+    pub fn read_async<T: bytemuck::Pod>(&self, ctx: &Ctx) -> ReadbackTicket<T> {
+        check_texel_type::<T>(&self.texture, "Texture::read_async");
+        let (sender, ticket) = ReadbackTicket::channel();
+        start_texture_read(ctx, &self.texture, sender);
+        ticket
+    }
 
-        let bytes_per_row_packed = width * 4;
-        let bytes_per_row_texture = if bytes_per_row_packed & 255 != 0 {
-            (bytes_per_row_packed & (!255)) + 256
-        } else {
-            bytes_per_row_packed
+    pub fn dispatch_count(&self, workgroup_size: (u32, u32)) -> [u32; 3] {
+        let size = self.texture.size();
+        assert_ne!(workgroup_size.0, 0, "Texture::dispatch_count: workgroup_size cannot be zero");
+        assert_ne!(workgroup_size.1, 0, "Texture::dispatch_count: workgroup_size cannot be zero");
+        let w_width = workgroup_size.0;
+        let w_height = workgroup_size.1;
+        [size.width.div_ceil(w_width), size.height.div_ceil(w_height), 1]
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Y4mChroma {
+    /// Chroma is subsampled 2x2.
+    C420,
+    /// Chroma is full resolution.
+    C444,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// A single uncompressed yuv4mpeg2 file, e.g. for `ffmpeg -i video.y4m`.
+    Y4m {
+        chroma: Y4mChroma,
+        frame_rate: u32,
+    },
+    /// One png per frame, written into a directory as `00000.png`, `00001.png`, etc.
+    #[cfg(feature = "images")]
+    PngSequence,
+}
+
+/// Writes frames to disk without a native video codec.
+/// Frames are 8 bit Rgba or Bgra, as returned by `Texture::read`.
+#[derive(Debug)]
+pub struct Recorder {
+    pub format: RecordingFormat,
+    pub path: std::path::PathBuf,
+    pub size: (u32, u32),
+    pub frame_count: u32,
+
+    file: Option<std::io::BufWriter<std::fs::File>>,
+    planes: [Vec<u8>; 3],
+}
+
+impl Recorder {
+    /// For `RecordingFormat::PngSequence`, `path` is a directory that will be created if needed.
+    pub fn new(path: &std::path::Path, size: (u32, u32), format: RecordingFormat) -> Result<Self, CtxError> {
+        use std::io::Write;
+
+        if size.0 == 0 || size.1 == 0 {
+            return Err(CtxError::Encode(format!("cannot record frames of size {}x{}", size.0, size.1)));
+        }
+
+        let (width, height) = (size.0 as usize, size.1 as usize);
+        let (file, planes) = match format {
+            RecordingFormat::Y4m { chroma, frame_rate } => {
+                if frame_rate == 0 {
+                    return Err(CtxError::Encode(String::from("frame_rate cannot be zero")));
+                }
+
+                let (chroma_len, chroma_tag) = match chroma {
+                    Y4mChroma::C420 => (width.div_ceil(2) * height.div_ceil(2), "C420jpeg"),
+                    Y4mChroma::C444 => (width * height, "C444"),
+                };
+                let planes = [vec![0; width * height], vec![0; chroma_len], vec![0; chroma_len]];
+
+                let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+                writeln!(writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 {}", width, height, frame_rate, chroma_tag)?;
+                (Some(writer), planes)
+            }
+            #[cfg(feature = "images")]
+            RecordingFormat::PngSequence => {
+                std::fs::create_dir_all(path)?;
+                (None, Default::default())
+            }
         };
 
-        let buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: bytes_per_row_texture as u64 * height as u64,
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        Ok(Recorder {
+            format,
+            path: path.to_owned(),
+            size,
+            frame_count: 0,
+            file,
+            planes,
+        })
+    }
 
-        let mut encoder = ctx.device.create_command_encoder(&Default::default());
-        encoder.copy_texture_to_buffer(
-            self.texture.as_image_copy(),
-            wgpu::ImageCopyBuffer {
-                buffer: &buffer,
-                layout: wgpu::ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: Some(bytes_per_row_texture),
-                    rows_per_image: None,
+    /// Reads the texture and writes it as the next frame.
+    /// The texture must have the recorder's size and a format supported by `write_frame`.
+    pub fn write_texture(&mut self, ctx: &Ctx, texture: &Texture) -> Result<(), CtxError> {
+        let texels = texture.read_to_vec(ctx);
+        self.write_frame(&texels, texture.texture.format())
+    }
+
+    /// `texels` must be tightly packed rows of the recorder's size in `format`,
+    /// which must be Rgba8Unorm or Bgra8Unorm, or their srgb variants. Anything else is an error.
+    pub fn write_frame(&mut self, texels: &[u8], format: wgpu::TextureFormat) -> Result<(), CtxError> {
+        use std::io::Write;
+
+        let bgra = match format.remove_srgb_suffix() {
+            wgpu::TextureFormat::Rgba8Unorm => false,
+            wgpu::TextureFormat::Bgra8Unorm => true,
+            _ => return Err(CtxError::UnsupportedFormat(format)),
+        };
+
+        let (width, height) = (self.size.0 as usize, self.size.1 as usize);
+        if texels.len() != width * height * 4 {
+            return Err(CtxError::Encode(format!(
+                "frame is {} bytes, but a {}x{} frame is {} bytes", texels.len(), width, height, width * height * 4
+            )));
+        }
+
+        match self.format {
+            RecordingFormat::Y4m { chroma, .. } => {
+                let [y, u, v] = &mut self.planes;
+                match chroma {
+                    Y4mChroma::C420 => rgba_to_yuv420(texels, bgra, width, height, y, u, v),
+                    Y4mChroma::C444 => rgba_to_yuv444(texels, bgra, y, u, v),
                 }
-            },
-            wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1
+
+                // the file is only taken in finish, which consumes self
+                let file = self.file.as_mut().unwrap();
+                file.write_all(b"FRAME\n")?;
+                for plane in self.planes.iter() {
+                    file.write_all(plane)?;
+                }
+            }
+            #[cfg(feature = "images")]
+            RecordingFormat::PngSequence => {
+                let file = self.path.join(format!("{:05}.png", self.frame_count));
+                let result = if bgra {
+                    lodepng::encode_file(file, &swap_red_blue(texels), width, height, lodepng::ColorType::RGBA, 8)
+                } else {
+                    lodepng::encode_file(file, texels, width, height, lodepng::ColorType::RGBA, 8)
+                };
+                result.map_err(|e| CtxError::Encode(e.to_string()))?;
             }
+        }
+
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    /// Flushes buffered frames. Dropping the recorder also flushes, but ignores errors.
+    pub fn finish(mut self) -> Result<(), CtxError> {
+        use std::io::Write;
+
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "images")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AnimationFormat {
+    /// Each frame is quantised to its own 256 colour palette.
+    Gif,
+    Apng,
+}
+
+/// Accumulates frames and writes them as an animated gif or png.
+#[cfg(feature = "images")]
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub format: AnimationFormat,
+    pub size: (u32, u32),
+    /// Gifs round this to the nearest 10ms.
+    pub frame_delay_ms: u16,
+    /// Number of times the animation is played, at least once, or `write` fails. `None` loops forever.
+    pub loop_count: Option<u16>,
+    /// Tightly packed 8 bit Rgba frames.
+    pub frames: Vec<Vec<u8>>,
+}
+
+#[cfg(feature = "images")]
+impl Animation {
+    pub fn new(format: AnimationFormat, size: (u32, u32), frame_delay_ms: u16, loop_count: Option<u16>) -> Self {
+        if format == AnimationFormat::Gif {
+            assert!(
+                size.0 <= u16::MAX as u32 && size.1 <= u16::MAX as u32,
+                "Animation::new: gif width and height cannot exceed 65535"
+            );
+        }
+        Animation { format, size, frame_delay_ms, loop_count, frames: Vec::new() }
+    }
+
+    /// Reads the texture and appends it as the next frame.
+    /// The texture must have the animation's size and a format supported by `push_frame`.
+    pub fn push_texture(&mut self, ctx: &Ctx, texture: &Texture) {
+        let texels = texture.read_to_vec(ctx);
+        self.push_frame(texels, texture.texture.format());
+    }
+
+    /// `texels` must be tightly packed rows of the animation's size in `format`,
+    /// which must be Rgba8Unorm or Bgra8Unorm, or their srgb variants.
+    pub fn push_frame(&mut self, texels: Vec<u8>, format: wgpu::TextureFormat) {
+        assert_eq!(
+            texels.len(), self.size.0 as usize * self.size.1 as usize * 4,
+            "Animation::push_frame: frame size does not match the animation size"
         );
-        
-        ctx.queue.submit(std::iter::once(encoder.finish()));
 
-        let bytes_per_row_texture = bytes_per_row_texture as usize;
-        let bytes_per_row_packed = bytes_per_row_packed as usize;
-        let height = height as usize;
-
-        let arc_buffer = std::sync::Arc::new(buffer);
-        let callback_buffer = arc_buffer.clone();
-
-        arc_buffer.slice(..)
-            .map_async(
-                wgpu::MapMode::Read,
-                move |res| {
-                    match res {
-                        Ok(_) => (),
-                        Err(_) => {
-                            eprintln!("texture read failed");
-                            return;
-                        }
-                    };
+        let rgba = match format.remove_srgb_suffix() {
+            wgpu::TextureFormat::Rgba8Unorm => texels,
+            wgpu::TextureFormat::Bgra8Unorm => swap_red_blue(&texels),
+            _ => panic!("Animation::push_frame: format must be Rgba8Unorm or Bgra8Unorm, or their srgb variants"),
+        };
+        self.frames.push(rgba);
+    }
 
-                    let texture = callback_buffer.slice(..).get_mapped_range();
+    pub fn write(&self, file: &std::path::Path) -> Result<(), CtxError> {
+        use std::io::Write;
 
-                    let mut buffer = vec![0; bytes_per_row_packed * height];
+        if self.loop_count == Some(0) {
+            return Err(CtxError::Encode("loop_count must be at least 1, or None to loop forever".to_string()));
+        }
 
-                    for y in 0..height {
-                        let dst = &mut buffer[y*bytes_per_row_packed..][..bytes_per_row_packed];
-                        let src = &texture[y*bytes_per_row_texture..][..bytes_per_row_packed];
-                        dst.copy_from_slice(src);
-                    }
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(file)?);
+        let encode_error = |e: &dyn std::error::Error| CtxError::Encode(e.to_string());
+
+        match self.format {
+            AnimationFormat::Gif => {
+                let (width, height) = (self.size.0 as u16, self.size.1 as u16);
+                let mut encoder = gif::Encoder::new(&mut writer, width, height, &[])
+                    .map_err(|e| encode_error(&e))?;
+                // gifs count repeats after the first play
+                let repeat = match self.loop_count {
+                    None => Some(gif::Repeat::Infinite),
+                    Some(1) => None,
+                    Some(n) => Some(gif::Repeat::Finite(n - 1)),
+                };
+                if let Some(repeat) = repeat {
+                    encoder.set_repeat(repeat).map_err(|e| encode_error(&e))?;
+                }
 
-                    match sender.send(buffer) {
-                        Ok(_) => (),
-                        Err(e) => eprintln!("texture data send failed: {}", e),
-                    }
+                let delay = ((self.frame_delay_ms as u32 + 5) / 10) as u16;
+                let mut scratch = Vec::new();
+                for rgba in self.frames.iter() {
+                    scratch.clone_from(rgba);
+                    let mut frame = gif::Frame::from_rgba_speed(width, height, &mut scratch, 10);
+                    frame.delay = delay;
+                    encoder.write_frame(&frame).map_err(|e| encode_error(&e))?;
                 }
-            );
+            }
+            AnimationFormat::Apng => {
+                let mut encoder = png::Encoder::new(&mut writer, self.size.0, self.size.1);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                // apngs count plays, with 0 meaning forever
+                encoder.set_animated(self.frames.len() as u32, self.loop_count.map_or(0, u32::from))
+                    .map_err(|e| encode_error(&e))?;
+                encoder.set_frame_delay(self.frame_delay_ms, 1000).map_err(|e| encode_error(&e))?;
+
+                let mut png_writer = encoder.write_header().map_err(|e| encode_error(&e))?;
+                for rgba in self.frames.iter() {
+                    png_writer.write_image_data(rgba).map_err(|e| encode_error(&e))?;
+                }
+                png_writer.finish().map_err(|e| encode_error(&e))?;
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
 
+impl RenderTexture {
+    /// Reads the colour texture as bytes. See `Texture::read_to_vec`.
+    pub fn read_to_vec(&self, ctx: &Ctx) -> Vec<u8> {
+        self.read_to_vec_as(ctx)
+    }
+
+    /// Reads the colour texture. See `Texture::read_to_vec_as`.
+    pub fn read_to_vec_as<T: bytemuck::Pod>(&self, ctx: &Ctx) -> Vec<T> {
+        self.read_async(ctx).wait(ctx)
+    }
+
+    /// Reads the colour texture. See `Texture::read`.
+    #[deprecated(note = "blocks until the GPU is idle; use `read_async` and poll the ticket instead")]
+    pub fn read(&self, ctx: &Ctx, sender: std::sync::mpsc::Sender<Vec<u8>>) {
+        start_texture_read(ctx, &self.texture, sender);
         ctx.device.poll(wgpu::Maintain::Wait);
     }
 
-    pub fn dispatch_count(&self, workgroup_size: (u32, u32)) -> [u32; 3] {
-        let size = self.texture.size();
-        assert_ne!(workgroup_size.0, 0, "Texture::dispatch_count: workgroup_size cannot be zero");
-        assert_ne!(workgroup_size.1, 0, "Texture::dispatch_count: workgroup_size cannot be zero");
-        let w_width = workgroup_size.0;
-        let w_height = workgroup_size.1;
-        [(size.width + w_width-1)/w_width, (size.height + w_height-1)/w_height, 1]
+    /// Starts reading the colour texture without blocking. See `ReadbackTicket`.
+    pub fn read_async<T: bytemuck::Pod>(&self, ctx: &Ctx) -> ReadbackTicket<T> {
+        check_texel_type::<T>(&self.texture, "RenderTexture::read_async");
+        let (sender, ticket) = ReadbackTicket::channel();
+        start_texture_read(ctx, &self.texture, sender);
+        ticket
+    }
+
+    /// Reads the depth of each pixel, with rows tightly packed.
+    pub fn read_depth_to_vec(&self, ctx: &Ctx) -> Vec<f32> {
+        self.read_depth_async(ctx).wait(ctx)
+    }
+
+    /// Starts reading the depth texture without blocking. See `ReadbackTicket`.
+    pub fn read_depth_async(&self, ctx: &Ctx) -> ReadbackTicket<f32> {
+        check_texel_type::<f32>(&self.depth_texture, "RenderTexture::read_depth_async");
+        let (sender, ticket) = ReadbackTicket::channel();
+        start_texture_read(ctx, &self.depth_texture, sender);
+        ticket
     }
 }
 
+/// The aspect that is read back, the depth of depth textures and everything otherwise.
+fn read_aspect(format: wgpu::TextureFormat) -> wgpu::TextureAspect {
+    if format.has_depth_aspect() { wgpu::TextureAspect::DepthOnly } else { wgpu::TextureAspect::All }
+}
+
+/// Checks that texels can be read back as `T`s.
+fn check_texel_type<T>(texture: &wgpu::Texture, caller: &str) {
+    let format = texture.format();
+    let Some(texel_size) = format.block_copy_size(Some(read_aspect(format))) else {
+        panic!("{}: {:?} textures cannot be copied to a buffer", caller, format);
+    };
+    let size = std::mem::size_of::<T>() as u32;
+    assert!(
+        size != 0 && texel_size % size == 0,
+        "{}: {:?} texels are {} bytes, which cannot be read as {} byte values", caller, format, texel_size, size
+    );
+}
+
+/// Copies the first mip level as tightly packed rows of texels, or blocks for compressed formats.
+/// Nothing is sent until the device is polled.
+fn start_texture_read(ctx: &Ctx, texture: &wgpu::Texture, sender: std::sync::mpsc::Sender<Vec<u8>>) {
+    let width = texture.width();
+    let height = texture.height();
+
+    let format = texture.format();
+    let aspect = read_aspect(format);
+    let Some(block_size) = format.block_copy_size(Some(aspect)) else {
+        panic!("Texture::read: {:?} textures cannot be copied to a buffer", format);
+    };
+    let (block_width, block_height) = format.block_dimensions();
+
+    let bytes_per_row_packed = width.div_ceil(block_width) * block_size;
+    let bytes_per_row_texture = bytes_per_row_packed.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let rows = height.div_ceil(block_height);
+
+    let size = bytes_per_row_texture as u64 * rows as u64;
+    let buffer = ctx.staging.take(&ctx.device, size, wgpu::MapMode::Read);
+
+    let mut encoder = ctx.device.create_command_encoder(&Default::default());
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row_texture),
+                rows_per_image: None,
+            }
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1
+        }
+    );
+    
+    ctx.queue.submit(std::iter::once(encoder.finish()));
+
+    let bytes_per_row_texture = bytes_per_row_texture as usize;
+    let bytes_per_row_packed = bytes_per_row_packed as usize;
+    let rows = rows as usize;
+
+    map_readback(&ctx.staging, buffer, size, sender, move |texture| {
+        let mut buffer = vec![0; bytes_per_row_packed * rows];
+
+        for y in 0..rows {
+            let dst = &mut buffer[y*bytes_per_row_packed..][..bytes_per_row_packed];
+            let src = &texture[y*bytes_per_row_texture..][..bytes_per_row_packed];
+            dst.copy_from_slice(src);
+        }
+
+        buffer
+    });
+}
+
 #[cfg(feature = "winit")]
 mod winit_things {
+    use super::{Input, WindowTask};
+
     #[derive(Debug, Copy, Clone)]
     pub enum WindowEvent<'a> { 
         Redraw {
@@ -2185,60 +5621,255 @@ mod winit_things {
     #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     pub enum WindowTaskEx { Redraw, Exit, }
 
-    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-    pub enum WindowTask { Exit }
-
     impl From<WindowTask> for WindowTaskEx {
         fn from(task: WindowTask) -> Self {
             match task { WindowTask::Exit => WindowTaskEx::Exit }
         }
     }
 
-    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-    pub struct MouseButtons {
-        pub left: Option<KeyState>,
-        pub middle: Option<KeyState>,
-        pub right: Option<KeyState>,
+    pub use winit::keyboard::KeyCode as Key;
+}
+
+#[cfg(feature = "winit")]
+pub use winit_things::*;
+
+/// Keys only come from a window, so without the `winit` feature there are none.
+#[cfg(not(feature = "winit"))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Key {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WindowTask { Exit }
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MouseButtons {
+    pub left: Option<KeyState>,
+    pub middle: Option<KeyState>,
+    pub right: Option<KeyState>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyState {
+    JustPressed,
+    Held,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub state: KeyState,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Input<'a> {
+    pub key_events: &'a [KeyEvent],
+    pub mouse_position: Option<(f32, f32)>,
+    
+    /// positive -> scroll down, negative -> scroll up
+    pub mouse_scroll: f32,
+    pub mouse_buttons: MouseButtons,
+}
+
+impl<'a> Input<'a> {
+    pub fn just_pressed(&self, key: Key) -> bool {
+        self.key_events.iter().any(|event| event.key == key && event.state == KeyState::JustPressed)
     }
 
-    pub use winit::keyboard::KeyCode as Key;
+    pub fn held(&self, key: Key) -> bool {
+        self.key_events.iter().any(|event| event.key == key)
+    }
+}
 
-    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-    pub enum KeyState {
-        JustPressed,
-        Held,
+/// How png texels are stored in a texture format.
+#[cfg(feature = "images")]
+#[derive(Debug, PartialEq)]
+struct PngFormat {
+    channels: usize,
+    bgra: bool,
+    channel: PngChannel,
+    /// Colours are decoded from sRGB to linear values.
+    linear: bool,
+    /// The largest value of the decoded png's channels, 65535 if they are kept at 16 bits.
+    max: u16,
+}
+
+#[cfg(feature = "images")]
+#[derive(Debug, PartialEq)]
+enum PngChannel {
+    U8,
+    U32,
+    F32,
+}
+
+#[cfg(feature = "images")]
+impl PngFormat {
+    fn new(format: wgpu::TextureFormat, linear: bool) -> Result<Self, CtxError> {
+        use wgpu::TextureFormat as F;
+
+        let channels = match format {
+            F::R8Unorm | F::R8Uint | F::R32Float | F::R32Uint => 1,
+            F::Rg8Unorm | F::Rg8Uint | F::Rg32Float | F::Rg32Uint => 2,
+            F::Rgba8Unorm | F::Rgba8UnormSrgb | F::Bgra8Unorm | F::Bgra8UnormSrgb
+                | F::Rgba8Uint | F::Rgba32Float | F::Rgba32Uint => 4,
+            _ => return Err(CtxError::UnsupportedFormat(format)),
+        };
+        let channel = match format {
+            F::R32Float | F::Rg32Float | F::Rgba32Float => PngChannel::F32,
+            F::R32Uint | F::Rg32Uint | F::Rgba32Uint => PngChannel::U32,
+            _ => PngChannel::U8,
+        };
+        let integer = matches!(format, F::R8Uint | F::Rg8Uint | F::Rgba8Uint) || channel == PngChannel::U32;
+        Ok(PngFormat {
+            channels,
+            bgra: matches!(format, F::Bgra8Unorm | F::Bgra8UnormSrgb),
+            channel,
+            linear: linear && !integer && !format.is_srgb(),
+            max: u8::MAX as u16,
+        })
     }
 
-    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-    pub struct KeyEvent {
-        pub key: Key,
-        pub state: KeyState,
+    /// Whether a png with `bit_depth` bits per channel keeps them all in this format.
+    /// Only Float and Uint 32 bit formats have room for 16 bits.
+    fn keeps_bit_depth(&self, bit_depth: u32) -> bool {
+        bit_depth == 16 && self.channel != PngChannel::U8
     }
 
-    #[derive(Copy, Clone, Debug)]
-    pub struct Input<'a> {
-        pub key_events: &'a [KeyEvent],
-        pub mouse_position: Option<(f32, f32)>,
-        
-        /// positive -> scroll down, negative -> scroll up
-        pub mouse_scroll: f32,
-        pub mouse_buttons: MouseButtons,
+    /// The channels the format stores, in its order.
+    fn swizzle<C: Copy>(&self, [r, g, b, a]: [C; 4]) -> impl Iterator<Item = C> {
+        if self.bgra { [b, g, r, a] } else { [r, g, b, a] }.into_iter().take(self.channels)
     }
 
-    impl<'a> Input<'a> {
-        pub fn just_pressed(&self, key: Key) -> bool {
-            self.key_events.iter().any(|event| event.key == key && event.state == KeyState::JustPressed)
+    /// `0.0..=1.0`, decoded to linear values if the format is.
+    fn widen(&self, texel: [u16; 4]) -> [f32; 4] {
+        std::array::from_fn(|i| {
+            let c = texel[i] as f32 / self.max as f32;
+            if self.linear && i < 3 { srgb_to_linear(c) } else { c }
+        })
+    }
+}
+
+/// Encodes rgba texels, as stored in a png with channels up to `format.max`, in `format`.
+#[cfg(feature = "images")]
+fn png_texels(texels: &[[u16; 4]], format: &PngFormat) -> Vec<u8> {
+    let max = format.max as f32;
+    let mut data = Vec::with_capacity(texels.len() * format.channels * if format.channel == PngChannel::U8 { 1 } else { 4 });
+    for &texel in texels {
+        // Alpha is last in either order, and is never decoded.
+        for (i, c) in format.swizzle(texel).enumerate() {
+            let linear = format.linear && i < 3;
+            match format.channel {
+                PngChannel::U8 if linear => data.push(unit_to_channel(srgb_to_linear(c as f32 / max), 255) as u8),
+                PngChannel::U8 => data.push(c as u8),
+                PngChannel::U32 => data.extend_from_slice(&(c as u32).to_ne_bytes()),
+                PngChannel::F32 if linear => data.extend_from_slice(&srgb_to_linear(c as f32 / max).to_ne_bytes()),
+                PngChannel::F32 => data.extend_from_slice(&(c as f32 / max).to_ne_bytes()),
+            }
         }
+    }
+    data
+}
+
+/// Encodes `0.0..=1.0` rgba texels from `PngFormat::widen` in `format`.
+#[cfg(feature = "images")]
+fn png_texels_f32(texels: &[[f32; 4]], format: &PngFormat) -> Vec<u8> {
+    texels.iter()
+        .flat_map(|&texel| format.swizzle(texel))
+        .flat_map(|c| match format.channel {
+            PngChannel::U8 => vec![unit_to_channel(c, format.max) as u8],
+            PngChannel::U32 => (unit_to_channel(c, format.max) as u32).to_ne_bytes().to_vec(),
+            PngChannel::F32 => c.to_ne_bytes().to_vec(),
+        })
+        .collect()
+}
+
+#[cfg(feature = "images")]
+fn unit_to_channel(c: f32, max: u16) -> u16 {
+    (c * max as f32).round().clamp(0.0, max as f32) as u16
+}
 
-        pub fn held(&self, key: Key) -> bool {
-            self.key_events.iter().any(|event| event.key == key)
+#[cfg(feature = "images")]
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Halves an image with a 2x2 box filter, averaging sRGB colours in linear space.
+#[cfg(feature = "images")]
+fn downsample(texels: &[[f32; 4]], width: usize, height: usize, srgb: bool) -> (Vec<[f32; 4]>, usize, usize) {
+    let to_srgb = |c: f32| if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+
+    let (w, h) = ((width / 2).max(1), (height / 2).max(1));
+    let mut out = Vec::with_capacity(w * h);
+    for y in 0..h {
+        for x in 0..w {
+            let mut sum = [0.0f32; 4];
+            for (sx, sy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let texel = texels[(2*y + sy).min(height - 1) * width + (2*x + sx).min(width - 1)];
+                for c in 0..4 {
+                    sum[c] += if srgb && c < 3 { srgb_to_linear(texel[c]) } else { texel[c] };
+                }
+            }
+            out.push(std::array::from_fn(|c| {
+                let average = sum[c] / 4.0;
+                if srgb && c < 3 { to_srgb(average) } else { average }
+            }));
         }
     }
+    (out, w, h)
+}
 
+#[cfg(feature = "images")]
+fn swap_red_blue(texels: &[u8]) -> Vec<u8> {
+    texels.chunks_exact(4)
+        .flat_map(|texel| [texel[2], texel[1], texel[0], texel[3]])
+        .collect()
 }
 
-#[cfg(feature = "winit")]
-pub use winit_things::*;
+/// BT.601 limited range.
+fn rgb_to_yuv(r: i32, g: i32, b: i32) -> (u8, u8, u8) {
+    let y = ((66*r + 129*g + 25*b + 128) >> 8) + 16;
+    let u = ((-38*r - 74*g + 112*b + 128) >> 8) + 128;
+    let v = ((112*r - 94*g - 18*b + 128) >> 8) + 128;
+    (y as u8, u as u8, v as u8)
+}
+
+/// Converts packed 8 bit RGBA (or BGRA if `bgra` is set) to planar YUV with full resolution chroma.
+fn rgba_to_yuv444(rgba: &[u8], bgra: bool, y: &mut [u8], u: &mut [u8], v: &mut [u8]) {
+    let (ri, bi) = if bgra { (2, 0) } else { (0, 2) };
+    for (i, texel) in rgba.chunks_exact(4).enumerate() {
+        (y[i], u[i], v[i]) = rgb_to_yuv(texel[ri] as i32, texel[1] as i32, texel[bi] as i32);
+    }
+}
+
+/// Converts packed 8 bit RGBA (or BGRA if `bgra` is set) to planar YUV with 2x2 subsampled chroma.
+/// Chroma planes must be `width.div_ceil(2) * height.div_ceil(2)` long.
+fn rgba_to_yuv420(rgba: &[u8], bgra: bool, width: usize, height: usize, y: &mut [u8], u: &mut [u8], v: &mut [u8]) {
+    let (ri, bi) = if bgra { (2, 0) } else { (0, 2) };
+    for (texel, y) in rgba.chunks_exact(4).zip(y.iter_mut()) {
+        (*y, _, _) = rgb_to_yuv(texel[ri] as i32, texel[1] as i32, texel[bi] as i32);
+    }
+
+    let chroma_width = width.div_ceil(2);
+    for cy in 0..height.div_ceil(2) {
+        for cx in 0..chroma_width {
+            // odd sizes repeat the last row or column
+            let xs = [cx*2, (cx*2 + 1).min(width - 1)];
+            let ys = [cy*2, (cy*2 + 1).min(height - 1)];
+
+            let mut sum = [0i32; 3];
+            for ty in ys {
+                for tx in xs {
+                    let texel = &rgba[(ty * width + tx) * 4..][..4];
+                    sum[0] += texel[ri] as i32;
+                    sum[1] += texel[1] as i32;
+                    sum[2] += texel[bi] as i32;
+                }
+            }
+            let (_, cu, cv) = rgb_to_yuv((sum[0] + 2) / 4, (sum[1] + 2) / 4, (sum[2] + 2) / 4);
+            u[cy*chroma_width + cx] = cu;
+            v[cy*chroma_width + cx] = cv;
+        }
+    }
+}
 
 /// std Chain doesn't impl ExactSizeIterator >:(
 struct CustomChain<A, B> {
@@ -2263,7 +5894,7 @@ where
         let ret_a = match self.a {
             Some(ref mut a) => {
                 let ret = a.next();
-                if matches!(ret, None) {
+                if ret.is_none() {
                     self.a = None;
                 }
                 ret
@@ -2348,45 +5979,45 @@ pub enum StorageTextureFormat {
     Rgba32Float,
 }
 
-impl Into<wgpu::TextureFormat> for StorageTextureFormat {
-    fn into(self) -> wgpu::TextureFormat {
-        match self {
-            Self::R8Unorm     => wgpu::TextureFormat::R8Unorm    ,
-            Self::Rg8Unorm    => wgpu::TextureFormat::Rg8Unorm   ,
-            Self::Rgba8Unorm  => wgpu::TextureFormat::Rgba8Unorm ,
-            Self::R8Snorm     => wgpu::TextureFormat::R8Snorm    ,
-            Self::Rg8Snorm    => wgpu::TextureFormat::Rg8Snorm   ,
-            Self::Rgba8Snorm  => wgpu::TextureFormat::Rgba8Snorm ,
-            Self::R8Uint      => wgpu::TextureFormat::R8Uint     ,
-            Self::Rg8Uint     => wgpu::TextureFormat::Rg8Uint    ,
-            Self::Rgba8Uint   => wgpu::TextureFormat::Rgba8Uint  ,
-            Self::R8Sint      => wgpu::TextureFormat::R8Sint     ,
-            Self::Rg8Sint     => wgpu::TextureFormat::Rg8Sint    ,
-            Self::Rgba8Sint   => wgpu::TextureFormat::Rgba8Sint  ,
-            Self::R16Unorm    => wgpu::TextureFormat::R16Unorm   ,
-            Self::Rg16Unorm   => wgpu::TextureFormat::Rg16Unorm  ,
-            Self::Rgba16Unorm => wgpu::TextureFormat::Rgba16Unorm,
-            Self::R16Snorm    => wgpu::TextureFormat::R16Snorm   ,
-            Self::Rg16Snorm   => wgpu::TextureFormat::Rg16Snorm  ,
-            Self::Rgba16Snorm => wgpu::TextureFormat::Rgba16Snorm,
-            Self::R16Uint     => wgpu::TextureFormat::R16Uint    ,
-            Self::Rg16Uint    => wgpu::TextureFormat::Rg16Uint   ,
-            Self::Rgba16Uint  => wgpu::TextureFormat::Rgba16Uint ,
-            Self::R16Sint     => wgpu::TextureFormat::R16Sint    ,
-            Self::Rg16Sint    => wgpu::TextureFormat::Rg16Sint   ,
-            Self::Rgba16Sint  => wgpu::TextureFormat::Rgba16Sint ,
-            Self::R16Float    => wgpu::TextureFormat::R16Float   ,
-            Self::Rg16Float   => wgpu::TextureFormat::Rg16Float  ,
-            Self::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
-            Self::R32Uint     => wgpu::TextureFormat::R32Uint    ,
-            Self::Rg32Uint    => wgpu::TextureFormat::Rg32Uint   ,
-            Self::Rgba32Uint  => wgpu::TextureFormat::Rgba32Uint ,
-            Self::R32Sint     => wgpu::TextureFormat::R32Sint    ,
-            Self::Rg32Sint    => wgpu::TextureFormat::Rg32Sint   ,
-            Self::Rgba32Sint  => wgpu::TextureFormat::Rgba32Sint ,
-            Self::R32Float    => wgpu::TextureFormat::R32Float   ,
-            Self::Rg32Float   => wgpu::TextureFormat::Rg32Float  ,
-            Self::Rgba32Float => wgpu::TextureFormat::Rgba32Float,
+impl From<StorageTextureFormat> for wgpu::TextureFormat {
+    fn from(format: StorageTextureFormat) -> wgpu::TextureFormat {
+        match format {
+            StorageTextureFormat::R8Unorm     => wgpu::TextureFormat::R8Unorm    ,
+            StorageTextureFormat::Rg8Unorm    => wgpu::TextureFormat::Rg8Unorm   ,
+            StorageTextureFormat::Rgba8Unorm  => wgpu::TextureFormat::Rgba8Unorm ,
+            StorageTextureFormat::R8Snorm     => wgpu::TextureFormat::R8Snorm    ,
+            StorageTextureFormat::Rg8Snorm    => wgpu::TextureFormat::Rg8Snorm   ,
+            StorageTextureFormat::Rgba8Snorm  => wgpu::TextureFormat::Rgba8Snorm ,
+            StorageTextureFormat::R8Uint      => wgpu::TextureFormat::R8Uint     ,
+            StorageTextureFormat::Rg8Uint     => wgpu::TextureFormat::Rg8Uint    ,
+            StorageTextureFormat::Rgba8Uint   => wgpu::TextureFormat::Rgba8Uint  ,
+            StorageTextureFormat::R8Sint      => wgpu::TextureFormat::R8Sint     ,
+            StorageTextureFormat::Rg8Sint     => wgpu::TextureFormat::Rg8Sint    ,
+            StorageTextureFormat::Rgba8Sint   => wgpu::TextureFormat::Rgba8Sint  ,
+            StorageTextureFormat::R16Unorm    => wgpu::TextureFormat::R16Unorm   ,
+            StorageTextureFormat::Rg16Unorm   => wgpu::TextureFormat::Rg16Unorm  ,
+            StorageTextureFormat::Rgba16Unorm => wgpu::TextureFormat::Rgba16Unorm,
+            StorageTextureFormat::R16Snorm    => wgpu::TextureFormat::R16Snorm   ,
+            StorageTextureFormat::Rg16Snorm   => wgpu::TextureFormat::Rg16Snorm  ,
+            StorageTextureFormat::Rgba16Snorm => wgpu::TextureFormat::Rgba16Snorm,
+            StorageTextureFormat::R16Uint     => wgpu::TextureFormat::R16Uint    ,
+            StorageTextureFormat::Rg16Uint    => wgpu::TextureFormat::Rg16Uint   ,
+            StorageTextureFormat::Rgba16Uint  => wgpu::TextureFormat::Rgba16Uint ,
+            StorageTextureFormat::R16Sint     => wgpu::TextureFormat::R16Sint    ,
+            StorageTextureFormat::Rg16Sint    => wgpu::TextureFormat::Rg16Sint   ,
+            StorageTextureFormat::Rgba16Sint  => wgpu::TextureFormat::Rgba16Sint ,
+            StorageTextureFormat::R16Float    => wgpu::TextureFormat::R16Float   ,
+            StorageTextureFormat::Rg16Float   => wgpu::TextureFormat::Rg16Float  ,
+            StorageTextureFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+            StorageTextureFormat::R32Uint     => wgpu::TextureFormat::R32Uint    ,
+            StorageTextureFormat::Rg32Uint    => wgpu::TextureFormat::Rg32Uint   ,
+            StorageTextureFormat::Rgba32Uint  => wgpu::TextureFormat::Rgba32Uint ,
+            StorageTextureFormat::R32Sint     => wgpu::TextureFormat::R32Sint    ,
+            StorageTextureFormat::Rg32Sint    => wgpu::TextureFormat::Rg32Sint   ,
+            StorageTextureFormat::Rgba32Sint  => wgpu::TextureFormat::Rgba32Sint ,
+            StorageTextureFormat::R32Float    => wgpu::TextureFormat::R32Float   ,
+            StorageTextureFormat::Rg32Float   => wgpu::TextureFormat::Rg32Float  ,
+            StorageTextureFormat::Rgba32Float => wgpu::TextureFormat::Rgba32Float,
         }
     }
 }
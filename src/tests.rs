@@ -0,0 +1,369 @@
+use super::*;
+
+#[test]
+fn adapter_rank_follows_power_preference() {
+    use wgpu::{DeviceType, PowerPreference};
+
+    let rank = |power_preference| {
+        let mut types = [DeviceType::Cpu, DeviceType::IntegratedGpu, DeviceType::DiscreteGpu];
+        types.sort_by_key(|&device_type| adapter_rank(device_type, power_preference));
+        types
+    };
+    assert_eq!(rank(PowerPreference::HighPerformance)[0], DeviceType::DiscreteGpu);
+    assert_eq!(rank(PowerPreference::LowPower)[0], DeviceType::IntegratedGpu);
+    assert_eq!(rank(PowerPreference::None), [DeviceType::Cpu, DeviceType::IntegratedGpu, DeviceType::DiscreteGpu]);
+}
+
+#[test]
+fn rgba_to_yuv420_averages_chroma_and_repeats_odd_edges() {
+    let (width, height) = (3, 1);
+    let rgba = [255, 255, 255, 255, 0, 0, 0, 255, 255, 0, 0, 255];
+    let mut y = [0; 3];
+    let mut u = [0; 2];
+    let mut v = [0; 2];
+    rgba_to_yuv420(&rgba, false, width, height, &mut y, &mut u, &mut v);
+
+    assert_eq!(y, [235, 16, 82]);
+    // white and black average to grey, the last red column is averaged with itself
+    assert_eq!((u[0], v[0]), (128, 128));
+    let (_, red_u, red_v) = rgb_to_yuv(255, 0, 0);
+    assert_eq!((u[1], v[1]), (red_u, red_v));
+
+    let bgra = [0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 255, 255];
+    rgba_to_yuv420(&bgra, true, width, height, &mut y, &mut u, &mut v);
+    assert_eq!((y[0], u[0], v[0]), rgb_to_yuv(255, 0, 0));
+}
+
+#[test]
+fn rgba_to_yuv420_accepts_empty_frames() {
+    rgba_to_yuv420(&[], false, 0, 0, &mut [], &mut [], &mut []);
+    rgba_to_yuv420(&[], false, 4, 0, &mut [], &mut [], &mut []);
+}
+
+#[test]
+fn recorder_rejects_empty_frames() {
+    let path = std::env::temp_dir().join("ezcompute_empty.y4m");
+    let format = RecordingFormat::Y4m { chroma: Y4mChroma::C420, frame_rate: 30 };
+    assert!(matches!(Recorder::new(&path, (0, 4), format), Err(CtxError::Encode(_))));
+    assert!(!path.exists());
+}
+
+#[test]
+fn recorder_rejects_bad_settings_and_frames() {
+    let path = std::env::temp_dir().join("ezcompute_bad.y4m");
+    let format = RecordingFormat::Y4m { chroma: Y4mChroma::C420, frame_rate: 0 };
+    assert!(matches!(Recorder::new(&path, (2, 2), format), Err(CtxError::Encode(_))));
+
+    let format = RecordingFormat::Y4m { chroma: Y4mChroma::C420, frame_rate: 30 };
+    let mut recorder = Recorder::new(&path, (2, 2), format).unwrap();
+    let texels = [0u8; 16];
+    assert!(matches!(
+        recorder.write_frame(&texels, wgpu::TextureFormat::Rgba16Float),
+        Err(CtxError::UnsupportedFormat(wgpu::TextureFormat::Rgba16Float))
+    ));
+    assert!(matches!(recorder.write_frame(&texels[..12], wgpu::TextureFormat::Rgba8Unorm), Err(CtxError::Encode(_))));
+    recorder.write_frame(&texels, wgpu::TextureFormat::Rgba8Unorm).unwrap();
+    assert_eq!(recorder.frame_count, 1);
+    recorder.finish().unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn animation_loop_count_and_delay() {
+    let path = std::env::temp_dir().join("ezcompute_animation.gif");
+    let mut animation = Animation::new(AnimationFormat::Gif, (1, 1), u16::MAX, Some(3));
+    animation.push_frame(vec![255, 0, 0, 255], wgpu::TextureFormat::Rgba8Unorm);
+    animation.write(&path).unwrap();
+
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(std::fs::File::open(&path).unwrap()).unwrap();
+    let frame = decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!(frame.delay, 6554);
+    assert_eq!(decoder.repeat(), gif::Repeat::Finite(2));
+
+    let animation = Animation::new(AnimationFormat::Gif, (1, 1), 10, Some(0));
+    assert!(matches!(animation.write(&path), Err(CtxError::Encode(_))));
+    std::fs::remove_file(&path).unwrap();
+}
+
+fn virtual_files(files: &[(&'static str, &'static str)]) -> std::collections::HashMap<&'static str, &'static str> {
+    files.iter().copied().collect()
+}
+
+#[test]
+fn preprocess_defines_and_conditionals() {
+    let shader = ShaderSource::Str("#define SIZE 64\n#ifdef FAST\nlet a = SIZE;\n#else\nlet a = SIZE * 2u;\n#endif\nlet b = v.SIZE + 1SIZE;");
+    let out = preprocess(shader, &[], &virtual_files(&[])).unwrap();
+    assert_eq!(out.source, "let a = 64 * 2u;\nlet b = v.SIZE + 1SIZE;\n");
+
+    let out = preprocess(shader, &[("FAST", ""), ("SIZE", "8")], &virtual_files(&[])).unwrap();
+    assert_eq!(out.source, "let a = 64;\nlet b = v.SIZE + 1SIZE;\n");
+}
+
+#[test]
+fn preprocess_maps_lines_back_to_their_files() {
+    let includes = virtual_files(&[("common.wgsl", "// common\nfn common() {}")]);
+    let shader = ShaderSource::Str("fn main() {}\n#include \"common.wgsl\"\n#include \"common.wgsl\"\nfn end() {}");
+    let out = preprocess(shader, &[], &includes).unwrap();
+
+    assert_eq!(out.source, "fn main() {}\n// common\nfn common() {}\nfn end() {}\n");
+    assert_eq!(out.file_names, ["<str>", "common.wgsl"]);
+    assert_eq!(out.line_origins, [(0, 1), (1, 1), (1, 2), (0, 4)]);
+}
+
+#[test]
+fn preprocess_resolves_nested_virtual_includes_next_to_the_includer() {
+    let includes = virtual_files(&[
+        ("lib/noise.wgsl", "#include \"rng.wgsl\"\nfn noise() {}"),
+        ("lib/rng.wgsl", "fn lib_rng() {}"),
+        ("rng.wgsl", "fn root_rng() {}"),
+    ]);
+    let out = preprocess(ShaderSource::Str("#include \"lib/noise.wgsl\""), &[], &includes).unwrap();
+    assert_eq!(out.source, "fn lib_rng() {}\nfn noise() {}\n");
+    assert_eq!(out.file_names, ["<str>", "lib/noise.wgsl", "lib/rng.wgsl"]);
+}
+
+#[test]
+fn preprocess_resolves_files_from_virtual_includes_relative_to_the_real_includer() {
+    let dir = std::env::temp_dir().join("ezcompute_preprocess_includes");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.wgsl"), "#include \"virtual.wgsl\"").unwrap();
+    std::fs::write(dir.join("real.wgsl"), "fn real() {}").unwrap();
+
+    let includes = virtual_files(&[("virtual.wgsl", "#include \"real.wgsl\"")]);
+    let path: &'static std::path::Path = Box::leak(dir.join("main.wgsl").into_boxed_path());
+    let out = preprocess(ShaderSource::File(path), &[], &includes).unwrap();
+    assert_eq!(out.source, "fn real() {}\n");
+    assert_eq!(out.files, [dir.join("main.wgsl"), dir.join("real.wgsl")]);
+}
+
+#[test]
+fn preprocess_errors_point_at_the_directive() {
+    let includes = virtual_files(&[("broken.wgsl", "fn ok() {}\n#ifdef A\nfn a() {}")]);
+    let Err(CtxError::Shader(error)) = preprocess(ShaderSource::Str("#include \"broken.wgsl\""), &[], &includes) else {
+        panic!("expected a shader error");
+    };
+    assert_eq!(error.file, "broken.wgsl");
+    assert_eq!(error.location, Some((2, 1)));
+    assert_eq!(error.message, "missing #endif");
+}
+
+#[test]
+fn shader_errors_show_the_line_before_defines_were_substituted() {
+    let shader = ShaderSource::Str("fn f() {\n    let x = SCALE * vec2(1.0, 2.0) + missing;\n}");
+    let out = preprocess(shader, &[("SCALE", "vec2<f32>(0.5, 0.25)")], &virtual_files(&[])).unwrap();
+    let line = out.source.lines().nth(1).unwrap();
+    let column = line.find("missing").unwrap() as u32 + 1;
+
+    let error = out.error("no definition in scope".to_string(), Some(wgpu::naga::SourceLocation {
+        line_number: 2, line_position: column, offset: 0, length: 7,
+    }));
+    assert_eq!(error.location, Some((2, 38)));
+    assert_eq!(error.snippet, format!("  |\n2 |     let x = SCALE * vec2(1.0, 2.0) + missing;\n  | {}^^^^^^^", " ".repeat(37)));
+
+    // A location inside a substituted value underlines the define's name.
+    let error = out.error("bad".to_string(), Some(wgpu::naga::SourceLocation {
+        line_number: 2, line_position: 18, offset: 0, length: 3,
+    }));
+    assert_eq!(error.location, Some((2, 13)));
+    assert!(error.snippet.ends_with(&format!("| {}^^^^^", " ".repeat(12))));
+}
+
+#[test]
+fn variants_override_the_base_constants() {
+    let merged = merge_constants(&[("a", 1.0), ("b", 2.0)], &[("b", 3.0), ("c", 4.0)]);
+    assert_eq!(merged, [("a", 1.0), ("b", 3.0), ("c", 4.0)]);
+    assert_eq!(merge_constants(&[("a", 1.0)], &[]), [("a", 1.0)]);
+}
+
+#[test]
+fn invocations_round_up_to_whole_workgroups() {
+    assert_eq!(workgroup_count([64, 65, 1], [64, 8, 1]), [1, 9, 1]);
+    assert_eq!(workgroup_count([0, 1, 1], [64, 1, 1]), [0, 1, 1]);
+}
+
+#[test]
+fn dispatches_split_at_the_limit() {
+    let split: Vec<_> = split_dispatch([5, 2, 1], 2).collect();
+    assert_eq!(split, [
+        ([0, 0, 0], [2, 2, 1]),
+        ([2, 0, 0], [2, 2, 1]),
+        ([4, 0, 0], [1, 2, 1]),
+    ]);
+    assert_eq!(split_dispatch([3, 3, 1], 2).map(|(_, [x, y, z])| x * y * z).sum::<u32>(), 9);
+    assert_eq!(split_dispatch([4, 1, 1], 4).collect::<Vec<_>>(), [([0, 0, 0], [4, 1, 1])]);
+    assert_eq!(split_dispatch([0, 1, 1], 4).count(), 0);
+}
+
+#[test]
+fn overrides_in_workgroup_size_are_found() {
+    let source = "@id(0) override SIZE: u32 = 64;\n@compute @workgroup_size(SIZE, 1) fn main() {}";
+    let (range, name) = override_workgroup_size(source).unwrap();
+    assert_eq!((name, &source[range]), ("SIZE", "SIZE"));
+
+    assert!(override_workgroup_size("override SIZE: u32 = 64;\n@compute @workgroup_size(64) fn main() {}").is_none());
+    assert!(override_workgroup_size("const SIZE = 64u;\n@compute @workgroup_size(SIZE) fn main() {}").is_none());
+    assert!(override_workgroup_size("// never override SIZE\nconst SIZE = 64u;\n@compute @workgroup_size(SIZE) fn main() {}").is_none());
+    assert!(override_workgroup_size("override SIZE: u32;\n@compute @workgroup_size(/* SIZE */ 64) fn main() {}").is_none());
+}
+
+#[test]
+fn byte_ranges_are_bounded_by_the_length() {
+    assert_eq!(byte_range(2..5, 5, 12), Some(24..60));
+    assert_eq!(byte_range(5..5, 5, 12), Some(60..60));
+    assert_eq!(byte_range(4..6, 5, 12), None);
+    assert_eq!(byte_range(std::ops::Range { start: 3, end: 2 }, 5, 12), None);
+}
+
+#[test]
+fn reads_widen_to_the_copy_alignment_and_trim_back() {
+    assert_eq!(copy_range(4..12, 16), (4..12, 0..8));
+    assert_eq!(copy_range(2..3, 16), (0..4, 2..3));
+    assert_eq!(copy_range(6..10, 16), (4..12, 2..6));
+}
+
+#[test]
+fn cast_bytes_copies_into_aligned_elements() {
+    let bytes: Vec<u8> = [1u32, 2, 3].iter().flat_map(|n| n.to_ne_bytes()).collect();
+    assert_eq!(cast_bytes::<u32>(bytes.clone()), [1, 2, 3]);
+    assert_eq!(cast_bytes::<[u32; 3]>(bytes), [[1, 2, 3]]);
+    assert!(cast_bytes::<u64>(Vec::new()).is_empty());
+}
+
+#[test]
+#[should_panic]
+fn cast_bytes_rejects_partial_elements() {
+    cast_bytes::<u32>(vec![0; 6]);
+}
+
+#[test]
+fn staging_size_classes_waste_at_most_an_eighth() {
+    assert_eq!(StagingPool::size_class(1), 256);
+    assert_eq!(StagingPool::size_class(256), 256);
+    assert_eq!(StagingPool::size_class(257), 288);
+    assert_eq!(StagingPool::size_class(1024), 1024);
+    assert_eq!(StagingPool::size_class(65 << 20), 72 << 20);
+    for size in [300, 4097, 1 << 20, (1 << 20) + 1, 100_000_000] {
+        let class = StagingPool::size_class(size);
+        assert!(class >= size && class - size <= class / 8, "{} -> {}", size, class);
+    }
+}
+
+#[test]
+fn staging_stats_follow_buffers_through_the_pool() {
+    let mut pool = StagingPoolInner::<&str>::default();
+    assert_eq!(pool.take(256, wgpu::MapMode::Read), None);
+    assert_eq!(pool.take(512, wgpu::MapMode::Write), None);
+    pool.give_back("a", 256, wgpu::MapMode::Read);
+    pool.give_back("b", 512, wgpu::MapMode::Write);
+    assert_eq!(pool.stats, StagingStats {
+        allocations: 2, reuses: 0, evictions: 0, in_flight: 0, free_buffers: 2, free_bytes: 768,
+    });
+
+    // Free lists are kept apart by mode and size class.
+    assert_eq!(pool.take(256, wgpu::MapMode::Write), None);
+    assert_eq!(pool.take(256, wgpu::MapMode::Read), Some("a"));
+    assert_eq!(pool.stats, StagingStats {
+        allocations: 3, reuses: 1, evictions: 0, in_flight: 2, free_buffers: 1, free_bytes: 512,
+    });
+}
+
+#[test]
+fn staging_evicts_the_least_recently_returned() {
+    let mut pool = StagingPoolInner::<&str> { max_free_bytes: 1024, ..Default::default() };
+    for _ in 0..3 {
+        pool.take(512, wgpu::MapMode::Read);
+    }
+    pool.give_back("a", 512, wgpu::MapMode::Read);
+    pool.give_back("b", 512, wgpu::MapMode::Read);
+    pool.give_back("c", 512, wgpu::MapMode::Read);
+    assert_eq!((pool.stats.evictions, pool.stats.free_bytes), (1, 1024));
+    assert_eq!(pool.take(512, wgpu::MapMode::Read), Some("c"));
+    assert_eq!(pool.take(512, wgpu::MapMode::Read), Some("b"));
+    assert_eq!(pool.take(512, wgpu::MapMode::Read), None);
+
+    pool.give_back("d", 512, wgpu::MapMode::Write);
+    pool.evict(0);
+    assert_eq!((pool.stats.evictions, pool.stats.free_buffers, pool.stats.free_bytes), (2, 0, 0));
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn png_formats_are_checked_before_decoding() {
+    assert!(matches!(
+        PngFormat::new(wgpu::TextureFormat::Depth32Float, false),
+        Err(CtxError::UnsupportedFormat(wgpu::TextureFormat::Depth32Float))
+    ));
+    // Only Unorm and Float formats are decoded to linear values.
+    assert!(PngFormat::new(wgpu::TextureFormat::Rgba8Unorm, true).unwrap().linear);
+    assert!(PngFormat::new(wgpu::TextureFormat::R32Float, true).unwrap().linear);
+    assert!(!PngFormat::new(wgpu::TextureFormat::Rgba8UnormSrgb, true).unwrap().linear);
+    assert!(!PngFormat::new(wgpu::TextureFormat::Rgba8Uint, true).unwrap().linear);
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn png_texels_convert_to_each_format() {
+    use wgpu::TextureFormat as F;
+    let texels = [[10u16, 20, 30, 40], [255, 0, 128, 255]];
+    let format = |format| PngFormat::new(format, false).unwrap();
+
+    assert_eq!(png_texels(&texels, &format(F::Rgba8Unorm)), [10, 20, 30, 40, 255, 0, 128, 255]);
+    assert_eq!(png_texels(&texels, &format(F::Bgra8UnormSrgb)), [30, 20, 10, 40, 128, 0, 255, 255]);
+    assert_eq!(png_texels(&texels, &format(F::Rg8Uint)), [10, 20, 255, 0]);
+    assert_eq!(cast_bytes::<u32>(png_texels(&texels, &format(F::R32Uint))), [10, 255]);
+    assert_eq!(cast_bytes::<f32>(png_texels(&texels, &format(F::R32Float))), [10.0 / 255.0, 1.0]);
+
+    // Decoding keeps alpha and the ends of the range.
+    let linear = png_texels(&texels, &PngFormat::new(F::Rgba8Unorm, true).unwrap());
+    assert_eq!(linear, [1, 2, 3, 40, 255, 0, 55, 255]);
+
+    // The widened path used for mipmaps gives the same bytes.
+    for (format, linear) in [(F::Rgba8Unorm, false), (F::Bgra8Unorm, true), (F::Rg32Float, true), (F::Rgba32Uint, false)] {
+        let format = PngFormat::new(format, linear).unwrap();
+        let widened: Vec<[f32; 4]> = texels.iter().map(|&texel| format.widen(texel)).collect();
+        assert_eq!(png_texels_f32(&widened, &format), png_texels(&texels, &format), "{:?}", format);
+    }
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn png_texels_keep_16_bits_in_wide_formats() {
+    use wgpu::TextureFormat as F;
+    assert!(!PngFormat::new(F::Rgba8Unorm, false).unwrap().keeps_bit_depth(16));
+    assert!(!PngFormat::new(F::Rgba32Float, false).unwrap().keeps_bit_depth(8));
+
+    let texels = [[1000u16, 65535, 0, 65535]];
+    let format = |format| PngFormat { max: u16::MAX, ..PngFormat::new(format, false).unwrap() };
+    assert!(format(F::Rg32Uint).keeps_bit_depth(16));
+    assert_eq!(cast_bytes::<u32>(png_texels(&texels, &format(F::Rg32Uint))), [1000, 65535]);
+    assert_eq!(cast_bytes::<f32>(png_texels(&texels, &format(F::R32Float))), [1000.0 / 65535.0]);
+
+    for format in [format(F::Rgba32Uint), format(F::Rg32Float)] {
+        let widened: Vec<[f32; 4]> = texels.iter().map(|&texel| format.widen(texel)).collect();
+        assert_eq!(png_texels_f32(&widened, &format), png_texels(&texels, &format), "{:?}", format);
+    }
+}
+
+#[cfg(feature = "images")]
+#[test]
+fn downsample_averages_and_clamps_odd_edges() {
+    let texels = [
+        [0.0, 0.0, 0.0, 0.0], [1.0, 1.0, 1.0, 1.0], [0.5, 0.5, 0.5, 0.5],
+        [1.0, 1.0, 1.0, 1.0], [0.0, 0.0, 0.0, 0.0], [0.5, 0.5, 0.5, 0.5],
+    ];
+    let (out, w, h) = downsample(&texels, 3, 2, false);
+    assert_eq!((w, h), (1, 1));
+    assert_eq!(out, [[0.5; 4]]);
+
+    // Colours average in linear space for sRGB, alpha doesn't.
+    let (out, _, _) = downsample(&[[0.0; 4], [1.0; 4]], 2, 1, true);
+    assert!((out[0][0] - 0.735).abs() < 0.001, "{:?}", out);
+    assert_eq!(out[0][3], 0.5);
+
+    // The last level is 1x1, also for non-square images.
+    assert_eq!(downsample(&[[1.0; 4]; 4], 4, 1, false), (vec![[1.0; 4]; 2], 2, 1));
+    assert_eq!(downsample(&[[1.0; 4]], 1, 1, false), (vec![[1.0; 4]], 1, 1));
+}